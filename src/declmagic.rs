@@ -26,10 +26,15 @@ mod config;
 mod display;
 mod input;
 mod mechanics;
+mod navigation;
 mod physics;
 mod script;
 mod threaded_executer;
 
+/// Size of the window when the game starts.
+static WINDOW_WIDTH: uint = 1024;
+static WINDOW_HEIGHT: uint = 768;
+
 pub trait GameSystem {
     fn process(&mut self, state: &mut entities::EntitiesState, elapsed: &f64);
 }
@@ -52,13 +57,15 @@ impl Game {
     pub fn new<RL: resources::ResourcesLoader+Send+Share>(resources: RL)
         -> Game
     {
-        let display = Arc::new(display::managed_display::ManagedDisplay::new(display::raw::Display::new(1024, 768, "Game"), box resources.clone() as Box<resources::ResourcesLoader+Send+Share>));
+        let display = Arc::new(display::managed_display::ManagedDisplay::new(display::raw::Display::new(WINDOW_WIDTH, WINDOW_HEIGHT, "Game"), box resources.clone() as Box<resources::ResourcesLoader+Send+Share>));
 
         let mut state = entities::EntitiesState::new();
         entities::loader::load(&resources, "main", &mut state).unwrap();
 
-        let displaySystem = display::DisplaySystem::new(display.clone(), &state, |_|{});
-        let inputSystem = input::InputSystem::new(&state, |_|{});
+        let mut displaySystem = display::DisplaySystem::new(display.clone(), &state, |_|{});
+        let mut inputSystem = input::InputSystem::new(&state, |_|{});
+        displaySystem.set_window_size(WINDOW_WIDTH, WINDOW_HEIGHT);
+        inputSystem.set_window_size(WINDOW_WIDTH, WINDOW_HEIGHT);
         let physicsSystem = physics::PhysicsSystem::new(&state, |_|{});
         let mechanicsSystem = mechanics::MechanicsSystem::new(&state, resources.clone(), |_|{});
 
@@ -94,15 +101,23 @@ impl Game {
                 match self.display.recv() {
                     Some(display::raw::Closed) => break 'mainLoop,
                     Some(display::raw::Input(msg)) => inputMessages.push(msg),
-                    Some(_) => continue,
+                    Some(display::raw::Resized(width, height)) => {
+                        self.displaySystem.set_window_size(width, height);
+                        self.inputSystem.set_window_size(width, height);
+                    },
+                    // the position of the window doesn't matter
+                    Some(display::raw::Moved(_, _)) => continue,
                     None => break
                 };
             }
 
+            // input and scripts are not affected by the "timeModifier" components
+            let modifiedPeriod = period * mechanics::get_time_modifier(&self.state);
+
             self.inputSystem.process(&mut self.state, &period, inputMessages.as_slice(), |_|{});
-            self.physicsSystem.process(&mut self.state, &period, |_|{});
-            self.mechanicsSystem.process(&mut self.state, &period, |_|{});
-            self.displaySystem.draw(&mut self.state, &period, |_|{});
+            self.physicsSystem.process(&mut self.state, &modifiedPeriod, |_|{});
+            self.mechanicsSystem.process(&mut self.state, &modifiedPeriod, |_|{});
+            self.displaySystem.draw(&mut self.state, &modifiedPeriod, |_|{});
 
             for system in self.thirdPartySystems.mut_iter() {
                 system.process(&mut self.state, &period)
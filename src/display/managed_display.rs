@@ -70,10 +70,22 @@ impl ManagedDisplay {
 		self.display.recv()
 	}
 
+	pub fn set_viewport(&self, width: uint, height: uint) {
+		self.display.set_viewport(width, height)
+	}
+
 	pub fn swap_buffers(&self) {
 		self.display.swap_buffers()
 	}
 
+	pub fn set_depth_test(&self, enabled: bool) {
+		self.display.set_depth_test(enabled)
+	}
+
+	pub fn clear_depth(&self) {
+		self.display.clear_depth()
+	}
+
 	pub fn build_vertex_buffer1<T: super::raw::data_types::GLDataTuple>(&self, data: &[(T)], bindings: &[&str])
 		-> super::raw::VertexBuffer
 	{
@@ -86,6 +98,17 @@ impl ManagedDisplay {
 		self.display.build_vertex_buffer2(data, bindings)
 	}
 
+	pub fn build_vertex_buffer3<T1: super::raw::data_types::GLDataTuple, T2: super::raw::data_types::GLDataTuple, T3: super::raw::data_types::GLDataTuple>(&self, data: &[(T1, T2, T3)], bindings: &[&str])
+		-> super::raw::VertexBuffer
+	{
+		self.display.build_vertex_buffer3(data, bindings)
+	}
+
+	pub fn update_vertex_buffer<T>(&self, buffer: &super::raw::VertexBuffer, data: &[T])
+	{
+		self.display.update_vertex_buffer(buffer, data)
+	}
+
 	pub fn build_index_buffer<T: super::raw::data_types::GLDataType>(&self, prim: super::raw::PrimitiveType, data: &[T])
 		-> super::raw::IndexBuffer
 	{
@@ -108,6 +131,18 @@ impl ManagedDisplay {
 	{
 		self.display.draw(vertexBuffer, indexBuffer, program, uniforms)
 	}
+
+	pub fn draw_partial(&self, vertexBuffer: &super::raw::VertexBuffer, indexBuffer: &super::raw::IndexBuffer, indicesCount: uint, program: &super::raw::Program, uniforms: &super::raw::ProgramUniforms)
+	{
+		self.display.draw_partial(vertexBuffer, indexBuffer, indicesCount, program, uniforms)
+	}
+}
+
+impl Texture {
+	/// Returns true if both objects designate the same texture.
+	pub fn is_same(&self, other: &Texture) -> bool {
+		(self.texture.deref() as *const super::raw::Texture) == (other.texture.deref() as *const super::raw::Texture)
+	}
 }
 
 impl Deref<super::raw::Texture> for Texture {
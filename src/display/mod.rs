@@ -1,10 +1,13 @@
 use nalgebra::na::Mat4;
 
+pub use self::projection::{ aspect_ratio_correction, pixels_to_normalized, screen_to_world };
 pub use self::system::DisplaySystem;
 
 pub mod managed_display;
+mod projection;
 #[allow(dead_code)]
 pub mod raw;
+pub mod sprite_batcher;
 pub mod sprite_displayer;
 mod system;
 
@@ -0,0 +1,109 @@
+use nalgebra::na;
+use nalgebra::na::{ Inv, Mat4, Vec4 };
+
+/// Converts a position in pixels, relative to the top-left hand corner of the window,
+///  into coordinates between (-1, -1) at the bottom-left and (1, 1) at the top-right.
+pub fn pixels_to_normalized(x: f64, y: f64, width: uint, height: uint)
+    -> (f64, f64)
+{
+    if width == 0 || height == 0 {
+        return (0.0, 0.0);
+    }
+
+    ((2.0 * x / width as f64) - 1.0, 1.0 - (2.0 * y / height as f64))
+}
+
+/// Converts coordinates between (-1, -1) and (1, 1) into a position in the world,
+///  at z = 0, using the matrix of the camera.
+/// Returns None if the matrix can't be inverted.
+pub fn screen_to_world(camera: &Mat4<f32>, x: f64, y: f64)
+    -> Option<(f64, f64)>
+{
+    let mut inverse = camera.clone();
+    if !inverse.inv() {
+        return None;
+    }
+
+    // the point on the screen corresponds to a line in the world, going from
+    //  the near plane to the far plane
+    let unproject = |z: f32| -> Option<Vec4<f32>> {
+        let result = Vec4::new(x as f32, y as f32, z, 1.0) * inverse;
+        if result.w == 0.0 { None } else { Some(result / result.w) }
+    };
+
+    let (near, far) = match (unproject(-1.0), unproject(1.0)) {
+        (Some(n), Some(f)) => (n, f),
+        _ => return None
+    };
+
+    // finding where the line crosses z = 0 ; with orthographic cameras the line is
+    //  perpendicular to this plane, so x and y are the same all along the line
+    let ratio = if far.z == near.z { 0.0 } else { -near.z / (far.z - near.z) };
+    let point = near + (far - near) * ratio;
+
+    Some((point.x as f64, point.y as f64))
+}
+
+/// Returns the matrix that compensates the aspect ratio of the window, so that
+///  one unit has the same length horizontally and vertically.
+pub fn aspect_ratio_correction(width: uint, height: uint)
+    -> Mat4<f32>
+{
+    let ratio = if width == 0 || height == 0 { 1.0 } else { height as f32 / width as f32 };
+
+    Mat4::new(
+        ratio, 0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        0.0, 0.0, 0.0, 1.0
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::na::{ Eye, Mat4 };
+    use super::{ aspect_ratio_correction, pixels_to_normalized, screen_to_world };
+
+    #[test]
+    fn pixels() {
+        assert_eq!(pixels_to_normalized(0.0, 0.0, 800, 600), (-1.0, 1.0));
+        assert_eq!(pixels_to_normalized(400.0, 300.0, 800, 600), (0.0, 0.0));
+        assert_eq!(pixels_to_normalized(800.0, 600.0, 800, 600), (1.0, -1.0));
+        assert_eq!(pixels_to_normalized(10.0, 10.0, 0, 0), (0.0, 0.0));
+    }
+
+    #[test]
+    fn unprojection() {
+        let identity: Mat4<f32> = Eye::new_identity(4);
+        assert_eq!(screen_to_world(&identity, 0.5, -0.5), Some((0.5, -0.5)));
+
+        // a camera at (2, 3) that shows 2 units on each side
+        let camera = Mat4::new(
+            1.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            -2.0, -3.0, 0.0, 1.0
+        ) * Mat4::new(
+            0.5, 0.0, 0.0, 0.0,
+            0.0, 0.5, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            0.0, 0.0, 0.0, 1.0
+        );
+        assert_eq!(screen_to_world(&camera, 0.0, 0.0), Some((2.0, 3.0)));
+        assert_eq!(screen_to_world(&camera, 1.0, -0.5), Some((4.0, 2.0)));
+
+        // with the aspect ratio of a 200x100 window, the horizontal axis shows twice as many units
+        let corrected = camera * aspect_ratio_correction(200, 100);
+        assert_eq!(screen_to_world(&corrected, 1.0, 1.0), Some((6.0, 5.0)));
+
+        // a matrix that can't be inverted
+        let flat = Mat4::new(
+            1.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            0.0, 0.0, 0.0, 1.0
+        );
+        assert_eq!(screen_to_world(&flat, 0.0, 0.0), None);
+    }
+}
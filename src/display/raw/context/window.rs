@@ -16,7 +16,10 @@ pub struct Window {
     handle : *const libglfw3::GLFWwindow,
     commands : CommandsThread,
     eventsSender : Box<Sender<WindowEvent>>,
-    eventsReceiver : Receiver<WindowEvent>
+    eventsReceiver : Receiver<WindowEvent>,
+    // last known state of the axes and buttons of the joystick
+    joystickAxes : Vec<f64>,
+    joystickButtons : Vec<bool>
 }
 
 static mut GLFWInitialized: Once = ONCE_INIT;
@@ -168,6 +171,48 @@ extern fn keyCallback(window: *const libglfw3::GLFWwindow, key: c_int, scancode:
     }));
 }
 
+extern fn mouseButtonCallback(window: *const libglfw3::GLFWwindow, button: c_int, action: c_int, mods: c_int) {
+    let sender : &Sender<WindowEvent> = unsafe { transmute(libglfw3::glfwGetWindowUserPointer(window)) };
+
+    let inputButton = match button {
+        libglfw3::MOUSE_BUTTON_1 => ::input::Button0,
+        libglfw3::MOUSE_BUTTON_2 => ::input::Button1,
+        libglfw3::MOUSE_BUTTON_3 => ::input::Button2,
+        libglfw3::MOUSE_BUTTON_4 => ::input::Button3,
+        libglfw3::MOUSE_BUTTON_5 => ::input::Button4,
+        libglfw3::MOUSE_BUTTON_6 => ::input::Button5,
+        libglfw3::MOUSE_BUTTON_7 => ::input::Button6,
+        libglfw3::MOUSE_BUTTON_8 => ::input::Button7,
+        _ => return
+    };
+
+    sender.send(super::super::Input(match action {
+        libglfw3::PRESS => ::input::Pressed(inputButton),
+        libglfw3::RELEASE => ::input::Released(inputButton),
+        _ => return
+    }));
+}
+
+extern fn scrollCallback(window: *const libglfw3::GLFWwindow, x: ::libc::c_double, y: ::libc::c_double) {
+    let sender : &Sender<WindowEvent> = unsafe { transmute(libglfw3::glfwGetWindowUserPointer(window)) };
+
+    if x != 0.0 {
+        sender.send(super::super::Input(::input::AxisMoved(::input::WheelX, x as f64)));
+    }
+    if y != 0.0 {
+        sender.send(super::super::Input(::input::AxisMoved(::input::WheelY, y as f64)));
+    }
+}
+
+extern fn charCallback(window: *const libglfw3::GLFWwindow, character: ::libc::c_uint) {
+    let sender : &Sender<WindowEvent> = unsafe { transmute(libglfw3::glfwGetWindowUserPointer(window)) };
+
+    match ::std::char::from_u32(character as u32) {
+        Some(c) => sender.send(super::super::Input(::input::Text(c))),
+        None => ()
+    };
+}
+
 extern fn posCallback(window: *const libglfw3::GLFWwindow, x: c_int, y: c_int) {
     let sender : &Sender<WindowEvent> = unsafe { transmute(libglfw3::glfwGetWindowUserPointer(window)) };
     sender.send(super::super::Moved(x as uint, y as uint));
@@ -189,9 +234,45 @@ extern fn cursorPosCallback(window: *const libglfw3::GLFWwindow, x: ::libc::c_do
     unsafe { libglfw3::glfwGetWindowSize(window, &mut width, &mut height) };
 
     let sender : &Sender<WindowEvent> = unsafe { transmute(libglfw3::glfwGetWindowUserPointer(window)) };
-    let x = (2.0 * x / (width as f64)) - 1.0;
-    let y = (2.0 * (1.0 - (y / (height as f64)))) - 1.0;
-    sender.send(super::super::Input(::input::MouseMoved(x as f64, y as f64)));
+    let (x, y) = ::display::pixels_to_normalized(x as f64, y as f64, width as uint, height as uint);
+    sender.send(super::super::Input(::input::MouseMoved(x, y)));
+}
+
+/// Elements corresponding to the axes of the joystick.
+static JOYSTICK_AXES: [::input::Element, ..6] = [
+    ::input::XAxis, ::input::YAxis, ::input::Slider0, ::input::Slider1, ::input::Slider2, ::input::Slider3
+];
+
+/// Elements corresponding to the buttons of the joystick.
+/// Button0 to Button7 are used by the mouse.
+static JOYSTICK_BUTTONS: [::input::Element, ..8] = [
+    ::input::Button8, ::input::Button9, ::input::Button10, ::input::Button11,
+    ::input::Button12, ::input::Button13, ::input::Button14, ::input::Button15
+];
+
+/// Returns the values of the axes and buttons of the first joystick.
+/// Returns empty lists if there is no joystick.
+/// Must be called from the thread of the window.
+fn get_joystick_state() -> (Vec<f64>, Vec<bool>) {
+    unsafe {
+        if libglfw3::glfwJoystickPresent(libglfw3::JOYSTICK_1) == 0 {
+            return (Vec::new(), Vec::new());
+        }
+
+        let mut count: c_int = 0;
+        let axes = libglfw3::glfwGetJoystickAxes(libglfw3::JOYSTICK_1, &mut count);
+        let axes = if axes.is_null() { Vec::new() } else {
+            Vec::from_fn(count as uint, |i| *axes.offset(i as int) as f64)
+        };
+
+        let mut count: c_int = 0;
+        let buttons = libglfw3::glfwGetJoystickButtons(libglfw3::JOYSTICK_1, &mut count);
+        let buttons = if buttons.is_null() { Vec::new() } else {
+            Vec::from_fn(count as uint, |i| *buttons.offset(i as int) as c_int == libglfw3::PRESS)
+        };
+
+        (axes, buttons)
+    }
 }
 
 impl Window {
@@ -234,6 +315,9 @@ impl Window {
                 libglfw3::glfwSetWindowSizeCallback(handle, Some(sizeCallback));
                 libglfw3::glfwSetWindowCloseCallback(handle, Some(closeCallback));
                 libglfw3::glfwSetCursorPosCallback(handle, Some(cursorPosCallback));
+                libglfw3::glfwSetMouseButtonCallback(handle, Some(mouseButtonCallback));
+                libglfw3::glfwSetScrollCallback(handle, Some(scrollCallback));
+                libglfw3::glfwSetCharCallback(handle, Some(charCallback));
 
                 handle
             })
@@ -243,7 +327,9 @@ impl Window {
             handle: handle,
             commands: commands,
             eventsSender: txBox,
-            eventsReceiver: rx
+            eventsReceiver: rx,
+            joystickAxes: Vec::new(),
+            joystickButtons: Vec::new()
         }
     }
 
@@ -258,11 +344,14 @@ impl Window {
         });
     }
 
-    pub fn recv(&self) -> Option<WindowEvent> {
-        self.commands.exec(proc() {
+    pub fn recv(&mut self) -> Option<WindowEvent> {
+        let (axes, buttons) = self.commands.exec(proc() {
             unsafe { libglfw3::glfwPollEvents(); }
+            get_joystick_state()
         }).get();
-        
+
+        self.update_joystick(axes, buttons);
+
         match self.eventsReceiver.try_recv() {
             Ok(val) => Some(val),
             Err(Empty) => None,
@@ -270,6 +359,33 @@ impl Window {
         }
     }
 
+    /// Compares the state of the joystick with the last known one, and sends
+    ///  input messages for what has changed.
+    fn update_joystick(&mut self, axes: Vec<f64>, buttons: Vec<bool>) {
+        for i in range(0, JOYSTICK_AXES.len()) {
+            let old = if i < self.joystickAxes.len() { *self.joystickAxes.get(i) } else { 0.0 };
+            let new = if i < axes.len() { *axes.get(i) } else { 0.0 };
+
+            if old != new {
+                self.eventsSender.send(super::super::Input(::input::AxisMoved(JOYSTICK_AXES[i].clone(), new)));
+            }
+        }
+
+        for i in range(0, JOYSTICK_BUTTONS.len()) {
+            let old = i < self.joystickButtons.len() && *self.joystickButtons.get(i);
+            let new = i < buttons.len() && *buttons.get(i);
+
+            match (old, new) {
+                (false, true) => self.eventsSender.send(super::super::Input(::input::Pressed(JOYSTICK_BUTTONS[i].clone()))),
+                (true, false) => self.eventsSender.send(super::super::Input(::input::Released(JOYSTICK_BUTTONS[i].clone()))),
+                _ => ()
+            }
+        }
+
+        self.joystickAxes = axes;
+        self.joystickButtons = buttons;
+    }
+
     pub fn make_context_current(&self) {
         let handle = self.handle;
         self.commands.exec(proc() {
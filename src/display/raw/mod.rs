@@ -92,7 +92,9 @@ pub enum DepthFunction {
 }
 
 pub struct Display {
-	context : Arc<context::GLContext>
+	context : Arc<context::GLContext>,
+	// whether the next draws use depth testing
+	depthTest : std::sync::Mutex<bool>
 }
 
 pub struct Texture {
@@ -152,7 +154,8 @@ pub struct IndexBuffer {
 impl Display {
 	pub fn new(width: uint, height: uint, title: &str) -> Display {
 		Display {
-			context: Arc::new(context::GLContext::new(width, height, title))
+			context: Arc::new(context::GLContext::new(width, height, title)),
+			depthTest: std::sync::Mutex::new(false)
 		}
 	}
 
@@ -160,12 +163,34 @@ impl Display {
 		self.context.recv()
 	}
 
+	/// Sets the area of the window where the scene is drawn.
+	pub fn set_viewport(&self, width: uint, height: uint) {
+		self.context.exec(proc() {
+			gl::Viewport(0, 0, width as gl::types::GLsizei, height as gl::types::GLsizei);
+		});
+	}
+
 	pub fn swap_buffers(&self) {
 		self.context.swap_buffers();
 
 		self.context.exec(proc() {
 			gl::ClearColor(0.0, 0.0, 0.0, 1.0);
-			gl::Clear(gl::COLOR_BUFFER_BIT);
+			gl::ClearDepth(1.0);
+			gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+		});
+	}
+
+	/// Enables or disables depth testing for the next draws.
+	/// When enabled, a fragment is drawn only if its depth is lower or equal to the current one.
+	pub fn set_depth_test(&self, enabled: bool) {
+		*self.depthTest.lock() = enabled;
+	}
+
+	/// Resets the depth of all the pixels, so that the next draws are not hidden by the previous ones.
+	pub fn clear_depth(&self) {
+		self.context.exec(proc() {
+			gl::ClearDepth(1.0);
+			gl::Clear(gl::DEPTH_BUFFER_BIT);
 		});
 	}
 
@@ -216,6 +241,51 @@ impl Display {
 		}
 	}
 
+	pub fn build_vertex_buffer3<T1: data_types::GLDataTuple, T2: data_types::GLDataTuple, T3: data_types::GLDataTuple>(&self, data: &[(T1, T2, T3)], bindings: &[&str])
+		-> VertexBuffer
+	{
+		let elementsSize = std::mem::size_of_val(data.get(0).unwrap().ref0()) + std::mem::size_of_val(data.get(0).unwrap().ref1()) + std::mem::size_of_val(data.get(0).unwrap().ref2());
+		let bufferSize = data.len() * elementsSize;
+
+		let mut b = HashMap::new();
+		for offset in range(0, bindings.len()) {
+			let binding = bindings.get(offset).unwrap();
+			match offset {
+				0 => b.insert(binding.to_string(), ( data.get(0).unwrap().ref0().get_gl_type(), data.get(0).unwrap().ref0().get_num_elems() as i32, 0 )),
+				1 => b.insert(binding.to_string(), ( data.get(0).unwrap().ref1().get_gl_type(), data.get(0).unwrap().ref1().get_num_elems() as i32, data.get(0).unwrap().ref0().get_total_size() )),
+				2 => b.insert(binding.to_string(), ( data.get(0).unwrap().ref2().get_gl_type(), data.get(0).unwrap().ref2().get_num_elems() as i32, data.get(0).unwrap().ref0().get_total_size() + data.get(0).unwrap().ref1().get_total_size() )),
+				_ => fail!()
+			};
+		}
+
+		VertexBuffer {
+			display: self.context.clone(),
+			id: self.build_vertex_buffer(bufferSize, data.as_ptr() as *const libc::c_void),
+			elementsSize: elementsSize,
+			bindings: b
+		}
+	}
+
+	/// Replaces the content of a vertex buffer.
+	/// The data must have the same format as the one used to build the buffer.
+	/// This is meant for buffers whose content changes at each frame.
+	pub fn update_vertex_buffer<T>(&self, buffer: &VertexBuffer, data: &[T])
+	{
+		// otherwise the copy would read past the end of the data
+		assert_eq!(std::mem::size_of::<T>(), buffer.elementsSize);
+
+		let bufferSize = data.len() * buffer.elementsSize;
+		let dataPtr = data.as_ptr() as *const libc::c_void;
+		let id = buffer.id;
+
+		self.context.exec(proc() {
+			unsafe {
+				gl::BindBuffer(gl::ARRAY_BUFFER, id);
+				gl::BufferData(gl::ARRAY_BUFFER, bufferSize as gl::types::GLsizeiptr, dataPtr, gl::DYNAMIC_DRAW);
+			}
+		}).get();
+	}
+
 	fn build_vertex_buffer(&self, bufferSize: uint, data: *const libc::c_void)
 		-> gl::types::GLuint
 	{
@@ -460,20 +530,32 @@ impl Display {
 	}
 
 	pub fn draw(&self, vertexBuffer: &VertexBuffer, indexBuffer: &IndexBuffer, program: &Program, uniforms: &ProgramUniforms)
+	{
+		self.draw_partial(vertexBuffer, indexBuffer, indexBuffer.elementsCount, program, uniforms)
+	}
+
+	/// Same as `draw`, but only uses the first `indicesCount` elements of the index buffer.
+	pub fn draw_partial(&self, vertexBuffer: &VertexBuffer, indexBuffer: &IndexBuffer, indicesCount: uint, program: &Program, uniforms: &ProgramUniforms)
 	{
 		let vbID = vertexBuffer.id.clone();
 		let vbBindingsClone = vertexBuffer.bindings.clone();
 		let vbElementsSize = vertexBuffer.elementsSize.clone();
 		let ibID = indexBuffer.id.clone();
 		let ibPrimitives = indexBuffer.primitives.clone();
-		let ibElemCounts = indexBuffer.elementsCount.clone();
+		let ibElemCounts = ::std::cmp::min(indicesCount, indexBuffer.elementsCount);
 		let ibDataType = indexBuffer.dataType.clone();
+		let depthTest = *self.depthTest.lock();
 		let programID = program.id.clone();
 		let uniformsClone = uniforms.clone();
 
 		self.context.exec(proc() {
 			unsafe {
-    			gl::Disable(gl::DEPTH_TEST);
+    			if depthTest {
+    				gl::Enable(gl::DEPTH_TEST);
+    				gl::DepthFunc(gl::LEQUAL);
+    			} else {
+    				gl::Disable(gl::DEPTH_TEST);
+    			}
     			gl::Enable(gl::BLEND);
     			gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
 
@@ -0,0 +1,149 @@
+use nalgebra::na::Mat4;
+use std::cmp::{ max, min };
+use std::sync::Arc;
+use super::managed_display::ManagedDisplay;
+use super::raw::{ IndexBuffer, Program, ProgramUniforms, VertexBuffer };
+use super::sprite_displayer::SpriteDisplayer;
+
+static vertexShader: &'static str = "
+#version 110
+
+uniform mat4 uMatrix;
+
+// the third coordinate is the depth, which doesn't go through the matrix
+attribute vec3 iPosition;
+attribute vec2 iTexCoords;
+attribute vec4 iColor;
+
+varying vec2 vTexCoords;
+varying vec4 vColor;
+
+void main() {
+	gl_Position = vec4(iPosition.xy, 0.0, 1.0) * uMatrix;
+	gl_Position.z = iPosition.z * gl_Position.w;
+	vTexCoords = iTexCoords;
+	vColor = iColor;
+}
+";
+
+static fragmentShader: &'static str = "
+#version 110
+uniform sampler2D uTexture;
+varying vec2 vTexCoords;
+varying vec4 vColor;
+
+void main() {
+	gl_FragColor = texture2D(uTexture, vTexCoords) * vColor;
+
+	// transparent pixels must not hide what is behind them when depth testing is enabled
+	if (gl_FragColor.a == 0.0)
+		discard;
+}
+";
+
+/// Maximum number of sprites in a single draw call, so that the indices fit in a u16.
+static MAX_SPRITES_PER_DRAW: uint = 16384;
+
+/// Draws sprites in as few draw calls as possible.
+/// Consecutive sprites which use the same texture are drawn together, with their vertices
+///  gathered in a single vertex buffer.
+pub struct SpriteBatcher {
+	display: Arc<ManagedDisplay>,
+	program: Program,
+	uniforms: ProgramUniforms,
+	// rebuilt at each draw call
+	vertexBuffer: Option<VertexBuffer>,
+	// index buffer and number of sprites that it can contain
+	indexBuffer: Option<(IndexBuffer, uint)>
+}
+
+impl SpriteBatcher {
+	pub fn new(display: Arc<ManagedDisplay>) -> SpriteBatcher {
+		let vs = display.build_shader(super::raw::GLSL, super::raw::Vertex, vertexShader, "main").unwrap();
+		let fs = display.build_shader(super::raw::GLSL, super::raw::Fragment, fragmentShader, "main").unwrap();
+		let program = display.build_program(&[ &vs, &fs ]).unwrap();
+		let uniforms = program.build_uniforms();
+
+		SpriteBatcher {
+			display: display,
+			program: program,
+			uniforms: uniforms,
+			vertexBuffer: None,
+			indexBuffer: None
+		}
+	}
+
+	/// Draws the sprites in the given order.
+	/// Each sprite comes with the matrix that gives its position in the world, and with its
+	///  depth between -1 (front) and 1 (back), which is only used if depth testing is enabled.
+	pub fn draw(&mut self, sprites: &[(&SpriteDisplayer, Mat4<f32>, f32)], camera: &Mat4<f32>) {
+		let mut start = 0;
+
+		while start < sprites.len() {
+			// finding the sprites that can be drawn along with the first one
+			let &(first, _, _) = &sprites[start];
+			let mut end = start + 1;
+			while end < sprites.len() && end - start < MAX_SPRITES_PER_DRAW {
+				let &(sprite, _, _) = &sprites[end];
+				if !sprite.get_texture().is_same(first.get_texture()) {
+					break;
+				}
+				end += 1;
+			}
+
+			self.draw_batch(sprites.slice(start, end), camera);
+			start = end;
+		}
+	}
+
+	/// Draws sprites which all have the same texture.
+	fn draw_batch(&mut self, sprites: &[(&SpriteDisplayer, Mat4<f32>, f32)], camera: &Mat4<f32>) {
+		let mut vertices = Vec::with_capacity(sprites.len() * 4);
+		for &(sprite, ref matrix, depth) in sprites.iter() {
+			for &((x, y), texCoords, color) in sprite.get_vertices(matrix).iter() {
+				vertices.push(((x, y, depth), texCoords, color));
+			}
+		}
+
+		match self.vertexBuffer {
+			Some(ref vb) => self.display.update_vertex_buffer(vb, vertices.as_slice()),
+			None => ()
+		};
+		if self.vertexBuffer.is_none() {
+			self.vertexBuffer = Some(self.display.build_vertex_buffer3(vertices.as_slice(), &[ "iPosition", "iTexCoords", "iColor" ]));
+		}
+
+		self.reserve_indices(sprites.len());
+
+		let &(first, _, _) = &sprites[0];
+		let mut uniforms = self.uniforms.clone();
+		uniforms.set_texture("uTexture", first.get_texture().deref());
+		uniforms.set_value("uMatrix", camera.clone());
+
+		match (&self.vertexBuffer, &self.indexBuffer) {
+			(&Some(ref vb), &Some((ref ib, _))) =>
+				self.display.draw_partial(vb, ib, sprites.len() * 6, &self.program, &uniforms),
+			_ => ()
+		};
+	}
+
+	/// Makes sure that the index buffer is large enough for the given number of sprites.
+	fn reserve_indices(&mut self, spritesCount: uint) {
+		let previousCapacity = match self.indexBuffer {
+			Some((_, capacity)) if capacity >= spritesCount => return,
+			Some((_, capacity)) => capacity,
+			None => 0
+		};
+
+		// growing in advance to avoid rebuilding the buffer too often
+		let capacity = min(max(spritesCount, previousCapacity * 2), MAX_SPRITES_PER_DRAW);
+
+		let mut indices = Vec::with_capacity(capacity * 6);
+		for i in range(0, capacity) {
+			let first = (i * 4) as u16;
+			indices.push_all(&[ first, first + 1, first + 2, first, first + 2, first + 3 ]);
+		}
+
+		self.indexBuffer = Some((self.display.build_index_buffer(super::raw::TrianglesList, indices.as_slice()), capacity));
+	}
+}
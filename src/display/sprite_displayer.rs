@@ -1,82 +1,47 @@
-use nalgebra::na::{ Eye, Mat4 };
-use std::sync::{ Arc, Mutex };
+use nalgebra::na::{ Eye, Mat4, Vec4 };
+use std::sync::Arc;
 use std::num;
 use super::managed_display::{ ManagedDisplay, Texture };
-use super::Drawable;
-
-static vertexShader: &'static str = "
-#version 110
-
-uniform mat4 uMatrix;
-
-attribute vec2 iPosition;
-attribute vec2 iTexCoords;
-
-varying vec2 vTexCoords;
-
-void main() {
-	gl_Position = vec4(iPosition, 0.0, 1.0) * uMatrix;
-	vTexCoords = iTexCoords;
-}
-";
-
-static fragmentShader: &'static str = "
-#version 110
-uniform sampler2D uTexture;
-varying vec2 vTexCoords;
-
-void main() {
-	gl_FragColor = texture2D(uTexture, vTexCoords);
-}
-";
 
+/// A textured rectangle.
+/// Sprites are not drawn individually, but through a `SpriteBatcher`.
 pub struct SpriteDisplayer {
 	display: Arc<ManagedDisplay>,
 	insideMatrix: Mat4<f32>,
+	// part of the texture to display, as (x, y, width, height) in pixels
+	textureRect: Option<(f32, f32, f32, f32)>,
 	texture: Texture,
-	vertexBuffer: super::raw::VertexBuffer,
-	indexBuffer: super::raw::IndexBuffer,
-	program: super::raw::Program,
-	uniforms: Mutex<super::raw::ProgramUniforms>
+	// multiplied with the color of the texture, as RGBA
+	color: (f32, f32, f32, f32),
+	// rotation in radians, counter-clockwise, around the position of the entity
+	rotation: f32,
+	scale: (f32, f32),
+	flip: (bool, bool)
 }
 
 impl SpriteDisplayer {
 	pub fn new(display: Arc<ManagedDisplay>, resourceName: &str) -> Result<SpriteDisplayer, String> {
 		let texture = try!(display.load_texture(resourceName));
 
-		let vs = display.build_shader(super::raw::GLSL, super::raw::Vertex, vertexShader, "main").unwrap();
-		let fs = display.build_shader(super::raw::GLSL, super::raw::Fragment, fragmentShader, "main").unwrap();
-		let program = display.build_program(&[ &vs, &fs ]).unwrap();
-		let mut uniforms = program.build_uniforms();
-
-		let vb = display.build_vertex_buffer2(
-			&[
-				( (-1.0 as f32, -1.0 as f32), (0.0 as f32, 1.0 as f32) ),
-				( (-1.0 as f32,  1.0 as f32), (0.0 as f32, 0.0 as f32) ),
-				( ( 1.0 as f32,  1.0 as f32), (1.0 as f32, 0.0 as f32) ),
-				( ( 1.0 as f32, -1.0 as f32), (1.0 as f32, 1.0 as f32) )
-			],
-			&[ "iPosition", "iTexCoords" ]
-		);
-		let ib = display.build_index_buffer(super::raw::TrianglesList, &[ 0 as u16, 1, 2, 0, 2, 3 ]);
-
-		uniforms.set_texture("uTexture", texture.deref());
-
 		Ok(SpriteDisplayer {
 			display: display,
 			insideMatrix: Eye::new_identity(4),
+			textureRect: None,
 			texture: texture,
-			vertexBuffer: vb,
-			indexBuffer: ib,
-			program: program,
-			uniforms: Mutex::new(uniforms)
+			color: (1.0, 1.0, 1.0, 1.0),
+			rotation: 0.0,
+			scale: (1.0, 1.0),
+			flip: (false, false)
 		})
 	}
 
 	pub fn set_rectangle_coords(&mut self, leftCoord: Option<f32>, topCoord: Option<f32>, rightCoord: Option<f32>, bottomCoord: Option<f32>) {
 		self.insideMatrix = Eye::new_identity(4);
 
-		let heightToWidthRatio = (self.texture.get_height() as f32) / (self.texture.get_width() as f32);
+		let heightToWidthRatio = match self.textureRect {
+			Some((_, _, w, h)) if w != 0.0 => h / w,
+			_ => (self.texture.get_height() as f32) / (self.texture.get_width() as f32)
+		};
 
 		let (leftCoord, topCoord, rightCoord, bottomCoord) =
 			match (leftCoord, topCoord, rightCoord, bottomCoord) {
@@ -135,17 +100,93 @@ impl SpriteDisplayer {
 		self.insideMatrix.m42 = (topCoord + bottomCoord) / 2.0;
 	}
 
+	/// Sets the part of the texture to display, as (x, y, width, height) in pixels from
+	///  the top-left hand corner of the texture. None means the whole texture.
+	/// Must be called before `set_rectangle_coords`, as the ratio of the sprite depends on it.
+	pub fn set_texture_rect(&mut self, rect: Option<(f32, f32, f32, f32)>) {
+		self.textureRect = rect;
+	}
+
+	/// Returns the width and height of the texture in pixels.
+	pub fn get_texture_size(&self) -> (uint, uint) {
+		(self.texture.get_width(), self.texture.get_height())
+	}
+
+	/// Returns the matrix that converts the texture coordinates of the vertices into
+	///  the coordinates of the part of the texture to display.
+	fn get_texture_matrix(&self) -> Mat4<f32> {
+		let mut matrix: Mat4<f32> = Eye::new_identity(4);
+
+		match self.textureRect {
+			Some((x, y, w, h)) => {
+				let (width, height) = (self.texture.get_width() as f32, self.texture.get_height() as f32);
+				matrix.m11 = w / width;
+				matrix.m41 = x / width;
+				matrix.m22 = h / height;
+				matrix.m42 = y / height;
+			},
+			None => ()
+		};
+
+		matrix
+	}
+
 	pub fn set_resource(&mut self, resourceName: &str)
 	{
 		self.texture = self.display.load_texture(resourceName).unwrap();
-		self.uniforms.lock().set_texture("uTexture", self.texture.deref());
 	}
-}
 
-impl Drawable for SpriteDisplayer {
-	fn draw(&self, matrix: &Mat4<f32>) {
-		let mut uniforms = self.uniforms.lock();
-		uniforms.set_value("uMatrix", self.insideMatrix * matrix.clone());
-		self.display.draw(&self.vertexBuffer, &self.indexBuffer, &self.program, uniforms.deref());
+	/// Sets the color that is multiplied with the texture, as RGBA between 0 and 1.
+	pub fn set_color(&mut self, color: (f32, f32, f32, f32)) {
+		self.color = color;
+	}
+
+	/// Returns the color that is multiplied with the texture, as RGBA.
+	pub fn get_color(&self) -> (f32, f32, f32, f32) {
+		self.color
+	}
+
+	/// Sets the transformations applied to the sprite around the position of the entity.
+	pub fn set_transform(&mut self, rotation: f32, scale: (f32, f32), flipX: bool, flipY: bool) {
+		self.rotation = rotation;
+		self.scale = scale;
+		self.flip = (flipX, flipY);
+	}
+
+	pub fn get_texture<'a>(&'a self) -> &'a Texture {
+		&self.texture
+	}
+
+	/// Returns the four corners of the sprite as (position, texture coordinates, color),
+	///  with the position multiplied by the matrix.
+	pub fn get_vertices(&self, matrix: &Mat4<f32>) -> [((f32, f32), (f32, f32), (f32, f32, f32, f32)), ..4] {
+		let (scaleX, scaleY) = self.scale;
+		let (sin, cos) = (self.rotation.sin(), self.rotation.cos());
+		let transformMatrix = Mat4::new(
+			scaleX * cos, scaleX * sin, 0.0, 0.0,
+			-scaleY * sin, scaleY * cos, 0.0, 0.0,
+			0.0, 0.0, 1.0, 0.0,
+			0.0, 0.0, 0.0, 1.0
+		);
+
+		let finalMatrix = self.insideMatrix * transformMatrix * *matrix;
+		let texMatrix = self.get_texture_matrix();
+		let (flipX, flipY) = self.flip;
+		let color = self.color;
+
+		let vertex = |x: f32, y: f32, u: f32, v: f32| -> ((f32, f32), (f32, f32), (f32, f32, f32, f32)) {
+			let position = Vec4::new(x, y, 0.0, 1.0) * finalMatrix;
+			let u = if flipX { 1.0 - u } else { u };
+			let v = if flipY { 1.0 - v } else { v };
+			let texCoords = Vec4::new(u, v, 0.0, 1.0) * texMatrix;
+			((position.x / position.w, position.y / position.w), (texCoords.x, texCoords.y), color)
+		};
+
+		[
+			vertex(-1.0, -1.0, 0.0, 1.0),
+			vertex(-1.0,  1.0, 0.0, 0.0),
+			vertex( 1.0,  1.0, 1.0, 0.0),
+			vertex( 1.0, -1.0, 1.0, 1.0)
+		]
 	}
 }
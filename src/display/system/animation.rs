@@ -0,0 +1,209 @@
+use entities::{ EntitiesState, EntitiesHelper, EntityID, ComponentID };
+use entities::{ List, Number };
+use std::cmp::max;
+use std::collections::HashMap;
+use script;
+use log;
+
+/// Maximum number of frames of an animation described with "framesCount".
+static MAX_FRAMES_COUNT: f64 = 4096.0;
+
+/// Maximum index of a frame, so that the frames of an animation can't overflow.
+static MAX_FRAME: f64 = 1048576.0;
+
+/// Keeps track of the time since each "spriteAnimation" component has started.
+pub struct Animations {
+    animations: HashMap<ComponentID, AnimationState>
+}
+
+/// Description of an animation, as written in its "spriteAnimation" component.
+#[deriving(Clone, Show, PartialEq)]
+struct AnimationDescription {
+    // indices of the frames in the grid of the spriteDisplay
+    frames: Vec<uint>,
+    fps: f64,
+    looping: bool
+}
+
+struct AnimationState {
+    description: AnimationDescription,
+    time: f64,
+    finished: bool
+}
+
+impl Animations {
+    pub fn new()
+        -> Animations
+    {
+        Animations {
+            animations: HashMap::new()
+        }
+    }
+
+    /// Advances all the animations and returns the current frame of each entity.
+    /// When the description of an animation changes, it starts again from the beginning.
+    pub fn update(&mut self, state: &mut EntitiesState, elapsed: f64, log: |log::LogRecord|)
+        -> HashMap<EntityID, uint>
+    {
+        let mut listOfComponents = state.get_visible_native_components("spriteAnimation");
+        listOfComponents.sort();
+
+        // removing the animations whose component has disappeared
+        {
+            let toRemove = self.animations.keys()
+                .filter(|c| !listOfComponents.contains(*c))
+                .map(|c| c.clone())
+                .collect::<Vec<ComponentID>>();
+
+            for c in toRemove.iter() {
+                self.animations.remove(c);
+            }
+        }
+
+        let mut frames = HashMap::new();
+
+        for cmp in listOfComponents.move_iter() {
+            let owner = match state.get_owner(&cmp) { Ok(o) => o, Err(_) => continue };
+
+            let description = match read_description(state, &cmp) {
+                Ok(d) => d,
+                Err(msg) => {
+                    log(log::LogRecord::new(log::Warning, format!("spriteAnimation component {} {}", cmp, msg)));
+                    continue
+                }
+            };
+
+            let restart = match self.animations.find(&cmp) {
+                Some(a) => a.description != description,
+                None => true
+            };
+
+            if restart {
+                self.animations.insert(cmp.clone(), AnimationState {
+                    description: description.clone(),
+                    time: 0.0,
+                    finished: false
+                });
+            } else {
+                self.animations.find_mut(&cmp).unwrap().time += elapsed;
+            }
+
+            let (frame, justFinished) = {
+                let animation = self.animations.find_mut(&cmp).unwrap();
+                let (frame, finished) = get_frame(&animation.description, animation.time);
+                let justFinished = finished && !animation.finished;
+                animation.finished = finished;
+                (frame, justFinished)
+            };
+
+            frames.insert(owner, frame);
+
+            if justFinished {
+                match state.get_as_string(&cmp, "scriptOnFinish") {
+                    Some(script) => { script::execute_mut(state, None, &cmp, &script.as_slice()).unwrap(); },
+                    None => ()
+                };
+            }
+        }
+
+        frames
+    }
+}
+
+/// Returns the frame to display after a given time, and whether the animation is finished.
+/// Looping animations never finish.
+fn get_frame(description: &AnimationDescription, time: f64)
+    -> (uint, bool)
+{
+    let count = description.frames.len();
+    let position = if description.fps <= 0.0 { 0 } else { (time * description.fps).floor() as uint };
+
+    if description.looping {
+        (*description.frames.get(position % count), false)
+    } else if position >= count {
+        (*description.frames.get(count - 1), true)
+    } else {
+        (*description.frames.get(position), false)
+    }
+}
+
+/// Returns the rectangle of a frame in a texture divided in a grid, as (x, y, width, height)
+///  in pixels. The frames are numbered from left to right, then from top to bottom.
+pub fn get_frame_rect(frame: uint, frameWidth: f64, frameHeight: f64, textureWidth: uint)
+    -> (f64, f64, f64, f64)
+{
+    let columns = if frameWidth <= 0.0 { 1 } else { max((textureWidth as f64 / frameWidth).floor() as uint, 1) };
+    ((frame % columns) as f64 * frameWidth, (frame / columns) as f64 * frameHeight, frameWidth, frameHeight)
+}
+
+/// Reads the "spriteAnimation" component, or returns why it is not valid.
+fn read_description(state: &EntitiesState, cmp: &ComponentID)
+    -> Result<AnimationDescription, String>
+{
+    let frames: Vec<uint> = match state.get_and_resolve(cmp, "frames") {
+        Ok(List(list)) => list.iter()
+            .filter_map(|f| match f { &Number(n) if n >= 0.0 && n <= MAX_FRAME => Some(n as uint), _ => None })
+            .collect(),
+        _ => match (state.get_as_number(cmp, "framesCount"), state.get_as_number(cmp, "firstFrame").unwrap_or(0.0)) {
+            (Some(count), first) if count >= 1.0 && count <= MAX_FRAMES_COUNT && first >= 0.0 && first <= MAX_FRAME => {
+                let first = first as uint;
+                range(first, first + count as uint).collect()
+            },
+            (Some(_), _) =>
+                return Err(format!("has a \"framesCount\" or a \"firstFrame\" element out of range")),
+            (None, _) => return Err(format!("has no valid \"frames\" or \"framesCount\" element"))
+        }
+    };
+
+    if frames.len() == 0 {
+        return Err(format!("has no valid \"frames\" or \"framesCount\" element"));
+    }
+
+    Ok(AnimationDescription {
+        frames: frames,
+        fps: state.get_as_number(cmp, "fps").unwrap_or(10.0),
+        looping: state.get_as_boolean(cmp, "loop").unwrap_or(true)
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use entities::{ EntitiesState, EntitiesHelper, Boolean, Number };
+    use super::{ Animations, get_frame_rect };
+
+    #[test]
+    fn frames() {
+        let mut state = EntitiesState::new();
+        let entity = state.create_entity(None, true);
+
+        let mut data = HashMap::new();
+        data.insert("firstFrame".to_string(), Number(4.0));
+        data.insert("framesCount".to_string(), Number(3.0));
+        data.insert("fps".to_string(), Number(2.0));
+        data.insert("loop".to_string(), Boolean(false));
+        let cmp = state.create_native_component(&entity, "spriteAnimation", data).unwrap();
+
+        let mut animations = Animations::new();
+        assert_eq!(animations.update(&mut state, 0.25, |_|{}).find(&entity), Some(&4));
+        assert_eq!(animations.update(&mut state, 0.5, |_|{}).find(&entity), Some(&5));
+        assert_eq!(animations.update(&mut state, 0.5, |_|{}).find(&entity), Some(&6));
+        assert_eq!(animations.update(&mut state, 10.0, |_|{}).find(&entity), Some(&6));
+
+        // changing the animation starts it again
+        state.set(&cmp, "loop", Boolean(true)).unwrap();
+        animations.update(&mut state, 0.0, |_|{});
+        assert_eq!(animations.update(&mut state, 1.5, |_|{}).find(&entity), Some(&4));
+
+        // too many frames
+        state.set(&cmp, "framesCount", Number(1e12)).unwrap();
+        assert_eq!(animations.update(&mut state, 0.0, |_|{}).find(&entity), None);
+    }
+
+    #[test]
+    fn grid() {
+        assert_eq!(get_frame_rect(0, 32.0, 48.0, 128), (0.0, 0.0, 32.0, 48.0));
+        assert_eq!(get_frame_rect(5, 32.0, 48.0, 128), (32.0, 48.0, 32.0, 48.0));
+    }
+}
@@ -1,5 +1,5 @@
 use entities::{ EntitiesState, EntitiesHelper, EntityID, ComponentID, NativeComponentType };
-use nalgebra::na::{ Mat4, Vec3, Eye };
+use nalgebra::na::{ Mat4, Vec3 };
 
 use std::collections::{ HashSet, HashMap };
 use std::sync::{ Arc, Mutex };
@@ -37,43 +37,44 @@ impl CustomDisplaySystem {
 		}
 	}
 
-	pub fn draw(&mut self, state: &EntitiesState, log: |log::LogRecord|)
+	/// Returns the list of the "customDisplay" components that can be drawn.
+	pub fn get_components(&self)
+		-> Vec<ComponentID>
 	{
-		self.update_custom_displayers(state);
-
-		let camera = super::DisplaySystem::get_camera(state).unwrap_or_else(|| {
-			//declmagic_warn!(self.logger, "no active camera on the scene");
-			Eye::new_identity(4)
-		});
-
-	 	for (cmp, element) in self.elements.mut_iter() {
-	 		let pos = ::physics::get_entity_position(state, &state.get_owner(cmp).unwrap());
-	 		let translationMatrix = Mat4::new(1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, pos.x, pos.y, pos.z, 1.0);
-	 		let finalMatrix = translationMatrix * camera;
-
-	 		if element.uniforms.is_some() {
-		 		for uniName in match state.get(cmp, "matrixUniforms") { Ok(&::entities::List(ref l)) => l.clone(), _ => Vec::new() }.move_iter() {
-		 			let mut uniLocked = element.uniforms.as_ref().unwrap().lock();
-
-		 			match uniName {
-		 				::entities::String(s) =>
-							uniLocked.set_value(s.as_slice(), finalMatrix),
-						_ => ()
-		 			}
+		self.elements.keys().map(|c| c.clone()).collect()
+	}
+
+	/// Draws the element of a "customDisplay" component, whose entity is at `pos`.
+	pub fn draw_element(&mut self, state: &EntitiesState, cmp: &ComponentID, pos: &Vec3<f32>, camera: &Mat4<f32>)
+	{
+		let element = match self.elements.find_mut(cmp) { Some(e) => e, None => return };
+
+		let translationMatrix = Mat4::new(1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, pos.x, pos.y, pos.z, 1.0);
+		let finalMatrix = translationMatrix * *camera;
+
+		if element.uniforms.is_some() {
+			for uniName in match state.get(cmp, "matrixUniforms") { Ok(&::entities::List(ref l)) => l.clone(), _ => Vec::new() }.move_iter() {
+				let mut uniLocked = element.uniforms.as_ref().unwrap().lock();
+
+				match uniName {
+					::entities::String(s) =>
+						uniLocked.set_value(s.as_slice(), finalMatrix),
+					_ => ()
 				}
 			}
+		}
 
-			match (&element.vertexBuffer, &element.indexBuffer, &element.program, &element.uniforms) {
-				(&Some(ref vb), &Some(ref ib), &Some(ref prg), &Some(ref uni)) => {
-					let uniLocked = uni.lock();
-					self.display.draw(vb, ib, prg, &*uniLocked)
-				},
-				_ => ()
-			}
+		match (&element.vertexBuffer, &element.indexBuffer, &element.program, &element.uniforms) {
+			(&Some(ref vb), &Some(ref ib), &Some(ref prg), &Some(ref uni)) => {
+				let uniLocked = uni.lock();
+				self.display.draw(vb, ib, prg, &*uniLocked)
+			},
+			_ => ()
 		}
 	}
 
-	fn update_custom_displayers(&mut self, state: &EntitiesState)
+	/// Creates, updates and removes the elements so that they match the "customDisplay" components.
+	pub fn update(&mut self, state: &EntitiesState)
 	{
 		// getting the list of all sprite displayer components
 		let listOfComponents = state.get_components_iter()
@@ -1,20 +1,27 @@
 use super::managed_display::ManagedDisplay;
 use entities::{ EntitiesState, EntitiesHelper, EntityID, ComponentID, NativeComponentType };
 use nalgebra::na;
-use nalgebra::na::{ Vec3, Eye };
+use nalgebra::na::Eye;
 use std::collections::{ HashSet, HashMap };
 use std::sync::Arc;
 use super::sprite_displayer::SpriteDisplayer;
-use super::Drawable;
+use super::sprite_batcher::SpriteBatcher;
 use physics;
 use log;
 
+mod animation;
 mod custom_display_system;
+mod order;
 
 pub struct DisplaySystem {
     display: Arc<ManagedDisplay>,
     customDisplay: custom_display_system::CustomDisplaySystem,
-    sprites: HashMap<ComponentID, (SpriteDisplayer, String)>
+    sprites: HashMap<ComponentID, (SpriteDisplayer, String)>,
+    batcher: SpriteBatcher,
+    animations: animation::Animations,
+    // custom displays which can't be drawn because of their layer, and which have already been warned about
+    refusedCustomDisplays: HashSet<ComponentID>,
+    windowSize: (uint, uint)
 }
 
 impl DisplaySystem {
@@ -29,35 +36,106 @@ impl DisplaySystem {
         DisplaySystem {
             display: display.clone(),
             customDisplay: customDisplaySystem,
-            sprites: HashMap::new()
+            sprites: HashMap::new(),
+            batcher: SpriteBatcher::new(display.clone()),
+            animations: animation::Animations::new(),
+            refusedCustomDisplays: HashSet::new(),
+            windowSize: (1, 1)
         }
     }
 
-    pub fn draw(&mut self, state: &EntitiesState, _: &f64, log: |log::LogRecord|)
+    /// Must be called when the window is resized, to update the viewport and the aspect ratio.
+    pub fn set_window_size(&mut self, width: uint, height: uint)
     {
-        self.update_sprite_displayers(state, |l| log(l));
+        self.display.set_viewport(width, height);
+        self.windowSize = (width, height);
+    }
+
+    pub fn draw(&mut self, state: &mut EntitiesState, elapsed: &f64, log: |log::LogRecord|)
+    {
+        let frames = self.animations.update(state, *elapsed, |l| log(l));
+        self.update_sprite_displayers(state, &frames, |l| log(l));
 
-        let camera = DisplaySystem::get_camera(state).unwrap_or_else(|| {
+        let camera = DisplaySystem::get_camera(state, self.windowSize).unwrap_or_else(|| {
             log(log::LogRecord::new(log::Warning, format!("No active camera on the scene")));
             Eye::new_identity(4)
         });
 
-        for (cmp, &(ref sprite, _)) in self.sprites.iter() {
-            let pos = physics::get_entity_position(state,
-                &state.get_owner(cmp).unwrap());
-            let translationMatrix = na::Mat4::new(
-                1.0, 0.0, 0.0, 0.0,
-                0.0, 1.0, 0.0, 0.0,
-                0.0, 0.0, 1.0, 0.0,
-                pos.x, pos.y, pos.z, 1.0
-            );
-            sprite.draw(&(translationMatrix * camera));
+        self.customDisplay.update(state);
+
+        // sorting everything that must be drawn
+        let depthTested = order::get_depth_tested_layers(state);
+
+        let mut keys = Vec::new();
+        for (cmp, &(ref sprite, ref texture)) in self.sprites.iter() {
+            let (_, _, _, alpha) = sprite.get_color();
+            let content = order::SpriteContent(texture.clone(), alpha < 1.0);
+            keys.extend(order::DrawKey::from_component(state, cmp, content).move_iter());
         }
+        let mut refused = HashSet::new();
+        for cmp in self.customDisplay.get_components().iter() {
+            match order::DrawKey::from_component(state, cmp, order::CustomContent) {
+                // the shader of a custom display doesn't know the depth of the sprites, so it
+                //  can't be ordered with them in a depth-tested layer
+                Some(ref k) if depthTested.contains(&k.layer) => {
+                    if !self.refusedCustomDisplays.contains(cmp) {
+                        log(log::LogRecord::new(log::Warning, format!("customDisplay component {} can't be drawn in layer {}, which uses depth testing", cmp, k.layer)));
+                    }
+                    refused.insert(cmp.clone());
+                },
+                Some(k) => keys.push(k),
+                None => ()
+            }
+        }
+        self.refusedCustomDisplays = refused;
+
+        let keys = order::sort_draw_order(keys, &depthTested);
+
+        // consecutive sprites of the same layer are given to the batcher together
+        let mut batch = Vec::new();
+        let mut currentLayer = None;
+
+        for &(ref key, depth) in keys.iter() {
+            if currentLayer != Some(key.layer) {
+                draw_batch(&mut self.batcher, &mut batch, &camera);
+                currentLayer = Some(key.layer);
 
-        self.customDisplay.draw(state, |l| log(l));
+                // each depth-tested layer starts with an empty depth buffer, so that
+                //  it is entirely drawn on top of the previous layers
+                let depthTest = depthTested.contains(&key.layer);
+                if depthTest { self.display.clear_depth(); }
+                self.display.set_depth_test(depthTest);
+            }
+
+            match key.content {
+                order::SpriteContent(_, _) => {
+                    let &(ref sprite, _) = self.sprites.find(&key.component).unwrap();
+                    let pos = &key.position;
+
+                    // z is only used for the order, the depth is given separately
+                    batch.push((sprite, na::Mat4::new(
+                        1.0, 0.0, 0.0, 0.0,
+                        0.0, 1.0, 0.0, 0.0,
+                        0.0, 0.0, 1.0, 0.0,
+                        pos.x, pos.y, 0.0, 1.0
+                    ), depth));
+                },
+
+                order::CustomContent => {
+                    draw_batch(&mut self.batcher, &mut batch, &camera);
+                    self.customDisplay.draw_element(state, &key.component, &key.position, &camera);
+                }
+            }
+        }
+
+        draw_batch(&mut self.batcher, &mut batch, &camera);
+        self.display.set_depth_test(false);
     }
 
-    fn update_sprite_displayers(&mut self, state: &EntitiesState, log: |log::LogRecord|)
+    /// Creates, updates and removes the sprites so that they match the "spriteDisplay" components.
+    /// `frames` contains the current frame of the entities which have a "spriteAnimation".
+    fn update_sprite_displayers(&mut self, state: &EntitiesState, frames: &HashMap<EntityID, uint>,
+                                log: |log::LogRecord|)
     {
         // getting the list of all sprite displayer components
         let listOfComponents = state
@@ -122,6 +200,27 @@ impl DisplaySystem {
                 *currTexName = textureName;
             }
 
+            // getting the part of the texture to display
+            let rect = match (state.get_and_resolve(component, "textureRect"),
+                              state.get_as_number(component, "frameWidth"),
+                              state.get_as_number(component, "frameHeight"))
+            {
+                (Ok(::entities::List(ref r)), _, _) if r.len() == 4 => match (r.get(0), r.get(1), r.get(2), r.get(3)) {
+                    (&::entities::Number(x), &::entities::Number(y), &::entities::Number(w), &::entities::Number(h))
+                        => Some((x, y, w, h)),
+                    _ => None
+                },
+                (_, Some(w), Some(h)) => {
+                    let frame = state.get_owner(component).ok()
+                        .and_then(|o| frames.find(&o).map(|f| *f))
+                        .unwrap_or(state.get_as_number(component, "frame").unwrap_or(0.0) as uint);
+                    let (textureWidth, _) = sprite.get_texture_size();
+                    Some(animation::get_frame_rect(frame, w, h, textureWidth))
+                },
+                _ => None
+            };
+            sprite.set_texture_rect(rect.map(|(x, y, w, h)| (x as f32, y as f32, w as f32, h as f32)));
+
             // getting coordinates
             sprite.set_rectangle_coords(
                 state.get_as_number(component, "leftX").map(|n| n as f32),
@@ -129,11 +228,21 @@ impl DisplaySystem {
                 state.get_as_number(component, "rightX").map(|n| n as f32),
                 state.get_as_number(component, "bottomY").map(|n| n as f32)
             );
+
+            // getting the color and the transformations
+            sprite.set_color(read_color(state, component));
+            sprite.set_transform(
+                state.get_as_number(component, "rotation").unwrap_or(0.0) as f32,
+                read_scale(state, component),
+                state.get_as_boolean(component, "flipX").unwrap_or(false),
+                state.get_as_boolean(component, "flipY").unwrap_or(false)
+            );
         }
     }
 
     /// Returns the camera matrix of the scene.
-    pub fn get_camera(state: &EntitiesState)
+    /// Returns the matrix of the active camera, for a window of the given size.
+    pub fn get_camera(state: &EntitiesState, (width, height): (uint, uint))
         -> Option<na::Mat4<f32>>
     {
         let cameraInfos = state
@@ -180,6 +289,92 @@ impl DisplaySystem {
             -position.x, -position.y, -position.z, 1.0
         );
 
-        Some(positionMatrix * matrix)
+        if state.get_as_boolean(&cameraComponent, "keepAspectRatio").unwrap_or(false) {
+            Some(positionMatrix * matrix * super::aspect_ratio_correction(width, height))
+        } else {
+            Some(positionMatrix * matrix)
+        }
+    }
+}
+
+/// Draws the sprites accumulated in `batch`, then empties it.
+fn draw_batch<'a>(batcher: &mut SpriteBatcher, batch: &mut Vec<(&'a SpriteDisplayer, na::Mat4<f32>, f32)>,
+                  camera: &na::Mat4<f32>)
+{
+    if batch.len() == 0 {
+        return;
+    }
+
+    batcher.draw(batch.as_slice(), camera);
+    batch.clear();
+}
+
+/// Reads the "color" and "opacity" elements of a "spriteDisplay" component, as RGBA.
+fn read_color(state: &EntitiesState, component: &ComponentID)
+    -> (f32, f32, f32, f32)
+{
+    let color = match state.get_and_resolve(component, "color") {
+        Ok(::entities::List(list)) => list.iter()
+            .filter_map(|c| match c { &::entities::Number(n) => Some(n as f32), _ => None })
+            .collect::<Vec<f32>>(),
+        _ => Vec::new()
+    };
+
+    let (r, g, b, a) = match color.as_slice() {
+        [r, g, b] => (r, g, b, 1.0),
+        [r, g, b, a] => (r, g, b, a),
+        _ => (1.0, 1.0, 1.0, 1.0)
+    };
+
+    let opacity = state.get_as_number(component, "opacity").unwrap_or(1.0) as f32;
+    (r, g, b, a * opacity)
+}
+
+/// Reads the "scale" element of a "spriteDisplay" component, which is either a number
+///  or a list of two numbers for the horizontal and vertical scales.
+fn read_scale(state: &EntitiesState, component: &ComponentID)
+    -> (f32, f32)
+{
+    match state.get_and_resolve(component, "scale") {
+        Ok(::entities::Number(s)) => (s as f32, s as f32),
+        Ok(::entities::List(ref list)) if list.len() == 2 => match (list.get(0), list.get(1)) {
+            (&::entities::Number(x), &::entities::Number(y)) => (x as f32, y as f32),
+            _ => (1.0, 1.0)
+        },
+        _ => (1.0, 1.0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use entities::{ EntitiesState, EntitiesHelper, FromProperty, List, Number, String };
+    use super::{ read_color, read_scale };
+
+    #[test]
+    fn color_and_scale() {
+        let mut state = EntitiesState::new();
+        let entity = state.create_entity(None, true);
+
+        let mut data = HashMap::new();
+        data.insert("texture".to_string(), String("player.png".to_string()));
+        data.insert("color".to_string(), FromProperty("tint".to_string()));
+        data.insert("opacity".to_string(), Number(0.5));
+        data.insert("scale".to_string(), List(vec!(Number(-1.0), Number(2.0))));
+        let cmp = state.create_native_component(&entity, "spriteDisplay", data).unwrap();
+
+        // the property doesn't exist yet
+        assert_eq!(read_color(&state, &cmp), (1.0, 1.0, 1.0, 0.5));
+        assert_eq!(read_scale(&state, &cmp), (-1.0, 2.0));
+
+        let mut data = HashMap::new();
+        data.insert("property".to_string(), String("tint".to_string()));
+        data.insert("value".to_string(), List(vec!(Number(1.0), Number(0.0), Number(0.0))));
+        state.create_native_component(&entity, "property", data).unwrap();
+        assert_eq!(read_color(&state, &cmp), (1.0, 0.0, 0.0, 0.5));
+
+        state.set(&cmp, "scale", Number(3.0)).unwrap();
+        assert_eq!(read_scale(&state, &cmp), (3.0, 3.0));
     }
 }
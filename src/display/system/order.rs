@@ -0,0 +1,195 @@
+use entities::{ EntitiesHelper, EntityID, ComponentID };
+use nalgebra::na::Vec3;
+use std::collections::HashSet;
+use physics;
+
+/// What an element of the scene draws.
+#[deriving(Clone, Show, PartialEq)]
+pub enum DrawContent {
+    // a sprite, with the name of its texture and whether it is translucent
+    SpriteContent(String, bool),
+    CustomContent
+}
+
+/// Information required to know when an element of the scene must be drawn.
+#[deriving(Clone, Show)]
+pub struct DrawKey {
+    pub layer: int,
+    // position of the entity
+    pub position: Vec3<f32>,
+    pub entity: EntityID,
+    pub component: ComponentID,
+    pub content: DrawContent
+}
+
+impl DrawKey {
+    /// Reads the layer and the position of a display component.
+    pub fn from_component<E: EntitiesHelper>(state: &E, component: &ComponentID, content: DrawContent)
+        -> Option<DrawKey>
+    {
+        let entity = match state.get_owner(component) { Ok(e) => e, Err(_) => return None };
+
+        Some(DrawKey {
+            layer: state.get_as_number(component, "layer").unwrap_or(0.0) as int,
+            position: physics::get_entity_position(state, &entity),
+            entity: entity,
+            component: component.clone(),
+            content: content
+        })
+    }
+
+    /// Order of the painter's algorithm: the lowest z first, then the oldest entities,
+    ///  then the oldest components.
+    fn painter_cmp(&self, other: &DrawKey)
+        -> Ordering
+    {
+        if self.layer != other.layer { return self.layer.cmp(&other.layer) }
+
+        match self.position.z.partial_cmp(&other.position.z) {
+            Some(Equal) | None => (),
+            Some(o) => return o
+        };
+
+        match self.entity.cmp(&other.entity) {
+            Equal => self.component.cmp(&other.component),
+            o => o
+        }
+    }
+}
+
+/// Returns the layers which use depth testing, according to the "displayLayer" components.
+pub fn get_depth_tested_layers<E: EntitiesHelper>(state: &E)
+    -> HashSet<int>
+{
+    state.get_visible_native_components("displayLayer")
+        .iter()
+        .filter(|c| state.get_as_boolean(*c, "depthTest").unwrap_or(false))
+        .map(|c| state.get_as_number(c, "layer").unwrap_or(0.0) as int)
+        .collect()
+}
+
+/// Sorts the elements in the order in which they must be drawn, and returns the depth
+///  of each of them, between -1 (front) and 1 (back).
+///
+/// The layers are drawn from the lowest to the highest.
+/// Inside a layer which doesn't use depth testing, the elements are sorted with the
+///  painter's algorithm and their depth is 0.
+/// Inside a layer which uses depth testing, the depth of the sprites depends on their z.
+/// The opaque sprites are drawn first, sorted by texture so that they can be drawn together.
+/// The translucent sprites are drawn after them with the painter's algorithm, as they must
+///  be blended with what is behind them.
+/// The layers which use depth testing must only contain sprites, as custom displays can't
+///  be ordered with them.
+pub fn sort_draw_order(mut keys: Vec<DrawKey>, depthTested: &HashSet<int>)
+    -> Vec<(DrawKey, f32)>
+{
+    keys.sort_by(|a, b| a.painter_cmp(b));
+
+    // computing the depth of the sprites of depth-tested layers from their rank in the layer
+    let mut result = Vec::with_capacity(keys.len());
+    let mut start = 0;
+    while start < keys.len() {
+        let layer = keys.get(start).layer;
+        let mut end = start;
+        while end < keys.len() && keys.get(end).layer == layer { end += 1; }
+
+        let count = keys.slice(start, end).iter()
+            .filter(|k| k.content != CustomContent).count();
+        let mut rank = 0u;
+
+        for key in keys.slice(start, end).iter() {
+            let depth = match key.content {
+                SpriteContent(_, _) if depthTested.contains(&layer) => {
+                    rank += 1;
+                    1.0 - 2.0 * (rank as f32 - 0.5) / count as f32
+                },
+                _ => 0.0
+            };
+            result.push((key.clone(), depth));
+        }
+
+        start = end;
+    }
+
+    result.sort_by(|&(ref a, _), &(ref b, _)| {
+        if a.layer != b.layer || !depthTested.contains(&a.layer) {
+            return a.painter_cmp(b);
+        }
+
+        match (&a.content, &b.content) {
+            (&SpriteContent(_, false), &SpriteContent(_, true)) => Less,
+            (&SpriteContent(_, true), &SpriteContent(_, false)) => Greater,
+            (&SpriteContent(ref t1, false), &SpriteContent(ref t2, false)) if t1 != t2 => t1.cmp(t2),
+            _ => a.painter_cmp(b)
+        }
+    });
+
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{ HashMap, HashSet };
+    use entities::{ EntitiesState, EntitiesHelper, Number };
+    use super::{ DrawKey, DrawContent, SpriteContent, CustomContent, sort_draw_order };
+
+    fn create(state: &mut EntitiesState, layer: f64, z: f64, content: DrawContent)
+        -> DrawKey
+    {
+        let entity = state.create_entity(None, true);
+
+        let mut data = HashMap::new();
+        data.insert("x".to_string(), Number(0.0));
+        data.insert("y".to_string(), Number(0.0));
+        data.insert("z".to_string(), Number(z));
+        state.create_native_component(&entity, "position", data).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("layer".to_string(), Number(layer));
+        let cmp = state.create_native_component(&entity, "spriteDisplay", data).unwrap();
+
+        DrawKey::from_component(state, &cmp, content).unwrap()
+    }
+
+    fn sprite(texture: &str)
+        -> DrawContent
+    {
+        SpriteContent(texture.to_string(), false)
+    }
+
+    #[test]
+    fn painter() {
+        let mut state = EntitiesState::new();
+        let hud = create(&mut state, 1.0, 0.0, CustomContent);
+        let front = create(&mut state, 0.0, 2.0, sprite("a"));
+        let back = create(&mut state, 0.0, 1.0, sprite("b"));
+        let sameZ = create(&mut state, 0.0, 1.0, sprite("a"));
+
+        let result = sort_draw_order(vec!(hud.clone(), front.clone(), sameZ.clone(), back.clone()), &HashSet::new());
+        let order = result.iter().map(|&(ref k, d)| (k.entity.clone(), d)).collect::<Vec<_>>();
+
+        assert_eq!(order, vec!((back.entity.clone(), 0.0), (sameZ.entity.clone(), 0.0),
+                               (front.entity.clone(), 0.0), (hud.entity.clone(), 0.0)));
+    }
+
+    #[test]
+    fn depth_test() {
+        let mut state = EntitiesState::new();
+        let a1 = create(&mut state, 0.0, 3.0, sprite("a"));
+        let b = create(&mut state, 0.0, 2.0, sprite("b"));
+        let a2 = create(&mut state, 0.0, 1.0, sprite("a"));
+        let translucent = create(&mut state, 0.0, 4.0, SpriteContent("a".to_string(), true));
+
+        let mut layers = HashSet::new();
+        layers.insert(0);
+
+        // the opaque sprites are grouped by texture, the translucent ones come after them,
+        //  and the ones with the highest z are in front
+        let result = sort_draw_order(vec!(a1.clone(), b.clone(), translucent.clone(), a2.clone()), &layers);
+        let order = result.iter().map(|&(ref k, d)| (k.entity.clone(), d)).collect::<Vec<_>>();
+
+        assert_eq!(order, vec!((a2.entity.clone(), 0.75), (a1.entity.clone(), -0.25),
+                               (b.entity.clone(), 0.25), (translucent.entity.clone(), -0.75)));
+    }
+}
@@ -83,17 +83,39 @@ pub trait EntitiesHelper {
     fn get_component_children(&self, component: &ComponentID)
         -> Result<Vec<ComponentID>, StateError>;
 
+    /// Ties the lifetime of an entity to a component.
+    /// When the component is destroyed, the entity is destroyed too.
+    fn attach_entity_to_component(&mut self, entity: &EntityID, component: &ComponentID)
+        -> Result<(), StateError>;
+
+    /// Breaks a link created with `attach_entity_to_component`.
+    fn detach_entity_from_component(&mut self, entity: &EntityID, component: &ComponentID)
+        -> Result<(), StateError>;
+
+    /// Ties the lifetime of a component to an entity.
+    /// When the entity is destroyed, the component is destroyed too.
+    fn attach_component_to_entity(&mut self, component: &ComponentID, entity: &EntityID)
+        -> Result<(), StateError>;
+
+    /// Breaks a link created with `attach_component_to_entity`.
+    fn detach_component_from_entity(&mut self, component: &ComponentID, entity: &EntityID)
+        -> Result<(), StateError>;
+
+    /// Returns the list of entities whose lifetime is tied to a component.
+    fn get_attached_entities(&self, component: &ComponentID)
+        -> Result<Vec<EntityID>, StateError>;
+
     /// Returns an element of a component.
     fn get<'a>(&'a self, id: &ComponentID, field: &str)
         -> Result<&'a Data, StateError>;
 
-    /// Returns the value of a property of an entity.
-    /// Reads the appropriate "property" or "propertyView" component.
-    /// Returns Ok(Empty) if the property is not found.
-    fn get_property_value(&self, id: &EntityID, propname: &str)
-        -> Result<Data, StateError>
+    /// Returns the "property" or "propertyView" component which currently
+    /// determines the value of a property of an entity.
+    /// Returns None if the entity doesn't have this property.
+    fn get_property_component(&self, id: &EntityID, propname: &str)
+        -> Option<ComponentID>
     {
-        let value = self
+        self
             .get_visible_native_components("property").move_iter()
             .chain(self.get_visible_native_components("propertyView").move_iter())
             .filter(|c| &self.get_owner(c).unwrap() == id)
@@ -107,43 +129,114 @@ pub trait EntitiesHelper {
                     Ok(Some(n)) => ((n * 1000f64) as int),
                     _ => 1000
                 })
-            .and_then(|c| {
-                let cmpType = match self.get_type(&c) {
-                    Ok(NativeComponentType(t)) => t.clone(),
-                    _ => fail!()
-                };
+    }
 
-                match cmpType.as_slice() {
-                    "property" =>
-                        match self.get(&c, "value") {
-                            Ok(&FromProperty(_)) => None,
-                            Ok(n) => Some(n.clone()),
-                            _ => None
-                        },
-                    "propertyView" => {
-                        let script = match self.get(&c, "script") {
-                            Ok(&FromProperty(_)) => return None,
-                            Ok(&String(ref n)) => n.clone(),
-                            _ => return None
-                        };
-                        match ::script::execute(self, &c, &script) {
-                            Ok(any::Number(val)) => Some(Number(val)),
-                            Ok(any::String(val)) => Some(String(val)),
-                            Ok(any::Boolean(val)) => Some(Boolean(val)),
-                            Ok(_) => unimplemented!(),
-                            Err(e) => fail!("{}", e)
-                        }
-                    },
-                    _ => fail!()
+    /// Returns the value given by a "property" or "propertyView" component.
+    /// Runs the script if the component is a "propertyView".
+    /// Returns Empty if the value could not be determined.
+    fn get_property_component_value(&self, component: &ComponentID)
+        -> Data
+    {
+        let cmpType = match self.get_type(component) {
+            Ok(NativeComponentType(t)) => t.clone(),
+            _ => fail!()
+        };
+
+        match cmpType.as_slice() {
+            "property" =>
+                match self.get(component, "value") {
+                    Ok(&FromProperty(_)) => Empty,
+                    Ok(n) => n.clone(),
+                    _ => Empty
+                },
+            "propertyView" => {
+                let script = match self.get(component, "script") {
+                    Ok(&String(ref n)) => n.clone(),
+                    _ => return Empty
+                };
+                match ::script::execute(self, component, &script) {
+                    Ok(any::Number(val)) => Number(val),
+                    Ok(any::String(val)) => String(val),
+                    Ok(any::Boolean(val)) => Boolean(val),
+                    Ok(_) => unimplemented!(),
+                    Err(e) => fail!("{}", e)
                 }
-            });
+            },
+            _ => fail!()
+        }
+    }
 
-        match value {
-            Some(v) => Ok(v),
+    /// Returns the value of a property of an entity.
+    /// Reads the appropriate "property" or "propertyView" component.
+    /// Returns Ok(Empty) if the property is not found.
+    fn get_property_value(&self, id: &EntityID, propname: &str)
+        -> Result<Data, StateError>
+    {
+        match self.get_property_component(id, propname) {
+            Some(c) => Ok(self.get_property_component_value(&c)),
             None => Ok(Empty)
         }
     }
 
+    /// Returns a number that changes every time a component of an entity
+    /// is created, modified or destroyed.
+    ///
+    /// Values coming from scripts or from "propertyView" components may change
+    /// without the revision changing. Returns None if changes can't be tracked.
+    fn get_entity_revision(&self, _: &EntityID)
+        -> Option<uint>
+    {
+        None
+    }
+
+    /// Returns a number that changes every time the value of a property
+    /// of an entity may have changed.
+    ///
+    /// Returns None if changes can't be tracked, for example because the
+    /// value comes from a "propertyView" script. In this situation the value
+    /// has to be read again every time.
+    fn get_property_revision(&self, _: &EntityID, _: &str)
+        -> Option<uint>
+    {
+        None
+    }
+
+    fn get_property_as_number(&self, id: &EntityID, propname: &str)
+        -> Option<f64>
+    {
+        match self.get_property_value(id, propname) {
+            Ok(Number(n)) => Some(n),
+            _ => None
+        }
+    }
+
+    fn get_property_as_string(&self, id: &EntityID, propname: &str)
+        -> Option<String>
+    {
+        match self.get_property_value(id, propname) {
+            Ok(String(s)) => Some(s),
+            _ => None
+        }
+    }
+
+    fn get_property_as_boolean(&self, id: &EntityID, propname: &str)
+        -> Option<bool>
+    {
+        match self.get_property_value(id, propname) {
+            Ok(Boolean(b)) => Some(b),
+            _ => None
+        }
+    }
+
+    fn get_property_as_entity(&self, id: &EntityID, propname: &str)
+        -> Option<EntityID>
+    {
+        match self.get_property_value(id, propname) {
+            Ok(Entity(e)) => Some(e),
+            _ => None
+        }
+    }
+
     /// Gets the value of a field of the component.
     /// Resolves it if the field is "FromProperty".
     fn get_and_resolve(&self, id: &ComponentID, field: &str)
@@ -1,6 +1,7 @@
 extern crate std;
 
 use super::EntitiesHelper;
+use std::cell::RefCell;
 use std::collections::HashMap;
 
 /// Identifier of an entity.
@@ -11,6 +12,16 @@ pub struct EntityID(uint);
 #[deriving(Clone, Show, Hash, PartialEq, PartialOrd, Eq, Ord)]
 pub struct ComponentID(uint);
 
+impl EntityID {
+    /// Returns the number that identifies the entity, for example to pass it to a script.
+    pub fn to_uint(&self)
+        -> uint
+    {
+        let &EntityID(id) = self;
+        id
+    }
+}
+
 /// An error while doing an operation on the state.
 #[deriving(Show)]
 pub enum StateError {
@@ -27,7 +38,15 @@ pub struct EntitiesState {
     next_component_id: ComponentID,
     next_entity_id: EntityID,
 
-    visible_components_of_native_type: HashMap<String, Vec<ComponentID>>
+    visible_components_of_native_type: HashMap<String, Vec<ComponentID>>,
+
+    // value of the properties that have already been read, invalidated when a
+    // "property" or "propertyView" component is created, modified or destroyed
+    property_cache: RefCell<HashMap<(EntityID, String), CachedProperty>>,
+
+    // revision of each property that has been invalidated at least once
+    property_revisions: HashMap<(EntityID, String), uint>,
+    next_property_revision: uint
 }
 
 struct EntityData {
@@ -35,6 +54,9 @@ struct EntityData {
     name: Option<String>,
     visible: bool,
 
+    // incremented every time a component of the entity is created, modified or destroyed
+    revision: uint,
+
     // components owned by the entity
     components: Vec<ComponentID>,
 
@@ -42,7 +64,10 @@ struct EntityData {
     components_of_type: Vec<ComponentID>,
 
     // list of parameters of the current entity
-    default_parameters: HashMap<String, Data>
+    default_parameters: HashMap<String, Data>,
+
+    // components that must be destroyed when this entity is destroyed
+    attached_components: Vec<ComponentID>
 }
 
 struct Component {
@@ -57,7 +82,10 @@ struct Component {
 
     parent: Option<ComponentID>,
     // when a component is destroyed, all children are destroyed too
-    children: Vec<ComponentID>
+    children: Vec<ComponentID>,
+
+    // entities that must be destroyed when this component is destroyed
+    attached_entities: Vec<EntityID>
 }
 
 enum ComponentData {
@@ -65,6 +93,14 @@ enum ComponentData {
     ComponentDataLink(ComponentID)
 }
 
+#[deriving(Clone)]
+enum CachedProperty {
+    // value of a "property" component
+    CachedValue(Data),
+    // "propertyView" component whose script must be run every time
+    CachedView(ComponentID)
+}
+
 /// Type of a component
 #[deriving(Clone,Show)]
 pub enum ComponentType {
@@ -72,7 +108,7 @@ pub enum ComponentType {
     EntityComponentType(EntityID)
 }
 
-#[deriving(Clone,Show)]
+#[deriving(Clone,Show,PartialEq)]
 pub enum Data {
     Number(f64),
     String(String),
@@ -123,7 +159,10 @@ impl EntitiesState {
             entities: HashMap::new(),
             next_component_id: ComponentID(1),
             next_entity_id: EntityID(1),
-            visible_components_of_native_type: HashMap::new()
+            visible_components_of_native_type: HashMap::new(),
+            property_cache: RefCell::new(HashMap::new()),
+            property_revisions: HashMap::new(),
+            next_property_revision: 1
         }
     }
 
@@ -162,6 +201,7 @@ impl EntitiesState {
             linked_from: Vec::new(),
             parent: Some(parent.clone()),
             children: Vec::new(),
+            attached_entities: Vec::new(),
             cmp_type: self.get_component_by_id(inherit).unwrap().cmp_type.clone()
         };
 
@@ -181,6 +221,9 @@ impl EntitiesState {
 
         match &mut self.next_component_id { &ComponentID(ref mut id) => (*id) += 1 };
 
+        self.touch_entity(owner);
+        self.invalidate_property_component(&newID);
+
         // recursively inheriting if necessary
         match self.get_component_by_id(inherit).unwrap().cmp_type.clone() {
             NativeComponentType(_) => (),
@@ -243,6 +286,88 @@ impl EntitiesState {
     fn has_parent(&self, id: &ComponentID) -> Result<bool, StateError> {
         Ok((try!(self.get_component_by_id(id))).parent.is_some())
     }
+
+    /// Returns the cache entry of a property, filling the cache if necessary.
+    fn get_cached_property(&self, id: &EntityID, propname: &str)
+        -> CachedProperty
+    {
+        let key = (id.clone(), propname.to_string());
+
+        match self.property_cache.borrow().find(&key) {
+            Some(entry) => return entry.clone(),
+            None => ()
+        };
+
+        let entry = match self.get_property_component(id, propname) {
+            None => CachedValue(Empty),
+            Some(c) => match self.get_type(&c) {
+                Ok(NativeComponentType(ref t)) if t.as_slice() == "propertyView" => CachedView(c),
+                _ => CachedValue(self.get_property_component_value(&c))
+            }
+        };
+
+        self.property_cache.borrow_mut().insert(key, entry.clone());
+        entry
+    }
+
+    /// If the component is a "property" or "propertyView", invalidates the
+    /// property it defines on all the entities that share its data.
+    fn invalidate_property_component(&mut self, id: &ComponentID)
+    {
+        match self.get_type(id) {
+            Ok(NativeComponentType(ref t)) if t.as_slice() == "property" || t.as_slice() == "propertyView" => (),
+            _ => return
+        };
+
+        let propname = match self.get(id, "property") {
+            Ok(&String(ref n)) => n.clone(),
+            _ => return
+        };
+
+        for owner in self.get_entities_sharing_data(id).iter() {
+            let key = (owner.clone(), propname.clone());
+            self.property_cache.borrow_mut().remove(&key);
+            self.property_revisions.insert(key, self.next_property_revision);
+            self.next_property_revision += 1;
+        }
+    }
+
+    /// Returns the owners of all the components that see the same data as this one.
+    fn get_entities_sharing_data(&self, id: &ComponentID)
+        -> Vec<EntityID>
+    {
+        // finding the component that holds the data
+        let mut origin = id.clone();
+        loop {
+            match self.get_component_by_id(&origin).unwrap().data {
+                ComponentDataLink(c) => origin = c,
+                ComponentDataNative(_) => break
+            }
+        }
+
+        // all the components linked to the origin see the same data
+        let mut owners = Vec::new();
+        let mut toVisit = vec!(origin);
+        loop {
+            let cmp = match toVisit.pop() { Some(c) => c, None => break };
+            let cmp = self.get_component_by_id(&cmp).unwrap();
+            if !owners.contains(&cmp.owner) {
+                owners.push(cmp.owner.clone());
+            }
+            toVisit.push_all(cmp.linked_from.as_slice());
+        }
+
+        owners
+    }
+
+    /// Increments the revision of an entity.
+    fn touch_entity(&mut self, id: &EntityID)
+    {
+        match self.entities.find_mut(id) {
+            Some(e) => e.revision += 1,
+            None => ()
+        }
+    }
 }
 
 impl EntitiesHelper for EntitiesState {
@@ -254,11 +379,13 @@ impl EntitiesHelper for EntitiesState {
         let entity = EntityData {
             name: name,
             visible: visible,
+            revision: 0,
 
             components: Vec::new(),
 
             components_of_type: Vec::new(),
-            default_parameters: std::collections::HashMap::new()
+            default_parameters: std::collections::HashMap::new(),
+            attached_components: Vec::new()
         };
 
         self.entities.insert(id, entity);
@@ -269,7 +396,7 @@ impl EntitiesHelper for EntitiesState {
     fn destroy_entity(&mut self, id: &EntityID)
         -> Result<(), StateError>
     {
-        let components_list = {
+        let (components_list, attached_components) = {
             let entity = try!(self.get_entity_by_id(id));
 
             if entity.components_of_type.len() != 0 {
@@ -277,7 +404,7 @@ impl EntitiesHelper for EntitiesState {
                 //return Err(format!("Cannot destroy entity with ID {} (name: {}) because it has components of its type", id, entity.name));
             }
 
-            entity.components.clone()
+            (entity.components.clone(), entity.attached_components.clone())
         };
 
         for cmp in components_list.iter() {
@@ -285,7 +412,29 @@ impl EntitiesHelper for EntitiesState {
             self.destroy_component(cmp).ok();
         }
 
+        // cleaning the properties cache and revisions, which have been updated while
+        //  destroying the components
+        {
+            let mut cache = self.property_cache.borrow_mut();
+            let toRemove: Vec<(EntityID, String)> = cache.keys().filter(|&&(ref e, _)| e == id).map(|k| k.clone()).collect();
+            for k in toRemove.iter() {
+                cache.remove(k);
+            }
+        }
+        {
+            let toRemove: Vec<(EntityID, String)> = self.property_revisions.keys().filter(|&&(ref e, _)| e == id).map(|k| k.clone()).collect();
+            for k in toRemove.iter() {
+                self.property_revisions.remove(k);
+            }
+        }
+
         self.entities.remove(id);
+
+        // the attached components may already have been destroyed
+        for cmp in attached_components.iter() {
+            self.destroy_component(cmp).ok();
+        }
+
         Ok(())
     }
 
@@ -304,6 +453,7 @@ impl EntitiesHelper for EntitiesState {
             linked_from: Vec::new(),
             parent: None,
             children: Vec::new(),
+            attached_entities: Vec::new(),
             cmp_type: NativeComponentType(typename.to_string())
         };
 
@@ -316,6 +466,9 @@ impl EntitiesHelper for EntitiesState {
             self.visible_components_of_native_type.insert_or_update_with(typename.to_string(), vec!(newID), |k,v| v.push(newID));
         }
 
+        self.touch_entity(owner);
+        self.invalidate_property_component(&newID);
+
         Ok(newID)
     }
 
@@ -337,11 +490,13 @@ impl EntitiesHelper for EntitiesState {
             linked_from: Vec::new(),
             parent: None,
             children: Vec::new(),
+            attached_entities: Vec::new(),
             cmp_type: EntityComponentType(typename.clone())
         };
 
         self.get_entity_by_id_mut(owner).unwrap().components.push(newID);
         self.get_entity_by_id_mut(typename).unwrap().components_of_type.push(newID);
+        self.touch_entity(owner);
 
         // creating the list of components to inherit
         let components_to_inherit: Vec<ComponentID> =
@@ -368,11 +523,13 @@ impl EntitiesHelper for EntitiesState {
     fn destroy_component(&mut self, id: &ComponentID)
         -> Result<(), StateError>
     {
-        let (children, linked, parent, cmp_type) = {
+        let (children, linked, parent, cmp_type, attached_entities) = {
             let cmp = try!(self.get_component_by_id(id));
-            (cmp.children.clone(), cmp.linked_from.clone(), cmp.parent.clone(), cmp.cmp_type.clone())
+            (cmp.children.clone(), cmp.linked_from.clone(), cmp.parent.clone(), cmp.cmp_type.clone(), cmp.attached_entities.clone())
         };
 
+        self.invalidate_property_component(id);
+
         if parent.is_some() {
             let mut p = self.get_component_by_id_mut(&parent.unwrap()).unwrap();
             let pos = p.children.iter().position(|c| *c == *id).unwrap();
@@ -393,12 +550,16 @@ impl EntitiesHelper for EntitiesState {
             let mut entity = self.get_entity_by_id_mut(&owner).unwrap();
             let pos = entity.components.iter().position(|e| *e == *id).unwrap();
             entity.components.remove(pos);
+            entity.revision += 1;
         }
 
         // removing from visible_components_of_native_type
         match &cmp_type {
             &NativeComponentType(ref t) => {
-                self.visible_components_of_native_type.find_mut(t).unwrap().retain(|e| e != id);
+                match self.visible_components_of_native_type.find_mut(t) {
+                    Some(list) => list.retain(|e| e != id),
+                    None => ()      // the owner is not visible
+                }
             },
             _ => ()
         }
@@ -406,12 +567,20 @@ impl EntitiesHelper for EntitiesState {
         // removing from components list
         self.components.remove(id);
 
+        // the attached entities may already have been destroyed
+        for entity in attached_entities.iter() {
+            self.destroy_entity(entity).ok();
+        }
+
         Ok(())
     }
 
     fn set(&mut self, id: &ComponentID, field: &str, data: Data)
         -> Result<(), StateError>
     {
+        // invalidating before and after in case the name of the property changes
+        self.invalidate_property_component(id);
+
         let mut idIter = id.clone();
 
         loop {
@@ -420,7 +589,7 @@ impl EntitiesHelper for EntitiesState {
             match &mut component.data {
                 &ComponentDataNative(ref mut val) => {
                     val.insert(field.to_string(), data);
-                    return Ok(());
+                    break;
                 },
                 &ComponentDataLink(c) => {
                     idIter = c.clone();
@@ -429,7 +598,12 @@ impl EntitiesHelper for EntitiesState {
             }
         }
 
-        unreachable!();
+        for owner in self.get_entities_sharing_data(id).iter() {
+            self.touch_entity(owner);
+        }
+
+        self.invalidate_property_component(id);
+        Ok(())
     }
 
     fn get_components_list(&self)
@@ -526,6 +700,79 @@ impl EntitiesHelper for EntitiesState {
     {
         Ok((try!(self.get_component_by_id(component))).children.clone())
     }
+
+    fn attach_entity_to_component(&mut self, entity: &EntityID, component: &ComponentID)
+        -> Result<(), StateError>
+    {
+        try!(self.get_entity_by_id(entity));
+
+        let cmp = try!(self.get_component_by_id_mut(component));
+        if !cmp.attached_entities.contains(entity) {
+            cmp.attached_entities.push(entity.clone());
+        }
+
+        Ok(())
+    }
+
+    fn detach_entity_from_component(&mut self, entity: &EntityID, component: &ComponentID)
+        -> Result<(), StateError>
+    {
+        let cmp = try!(self.get_component_by_id_mut(component));
+        cmp.attached_entities.retain(|e| e != entity);
+        Ok(())
+    }
+
+    fn attach_component_to_entity(&mut self, component: &ComponentID, entity: &EntityID)
+        -> Result<(), StateError>
+    {
+        try!(self.get_component_by_id(component));
+
+        let e = try!(self.get_entity_by_id_mut(entity));
+        if !e.attached_components.contains(component) {
+            e.attached_components.push(component.clone());
+        }
+
+        Ok(())
+    }
+
+    fn detach_component_from_entity(&mut self, component: &ComponentID, entity: &EntityID)
+        -> Result<(), StateError>
+    {
+        let e = try!(self.get_entity_by_id_mut(entity));
+        e.attached_components.retain(|c| c != component);
+        Ok(())
+    }
+
+    fn get_attached_entities(&self, component: &ComponentID)
+        -> Result<Vec<EntityID>, StateError>
+    {
+        Ok((try!(self.get_component_by_id(component))).attached_entities.clone())
+    }
+
+    fn get_property_value(&self, id: &EntityID, propname: &str)
+        -> Result<Data, StateError>
+    {
+        match self.get_cached_property(id, propname) {
+            CachedValue(value) => Ok(value),
+            CachedView(c) => Ok(self.get_property_component_value(&c))
+        }
+    }
+
+    fn get_entity_revision(&self, id: &EntityID)
+        -> Option<uint>
+    {
+        self.get_entity_by_id(id).ok().map(|e| e.revision)
+    }
+
+    fn get_property_revision(&self, id: &EntityID, propname: &str)
+        -> Option<uint>
+    {
+        match self.get_cached_property(id, propname) {
+            CachedView(_) => None,
+            CachedValue(_) =>
+                Some(self.property_revisions.find(&(id.clone(), propname.to_string())).map(|r| *r).unwrap_or(0))
+        }
+    }
 }
 
 
@@ -533,7 +780,8 @@ impl EntitiesHelper for EntitiesState {
 mod tests {
     use std::collections::HashMap;
 
-    use super::EntitiesState;
+    use super::{ EntitiesState, Number, String, Empty };
+    use super::super::EntitiesHelper;
 
     #[test]
     fn basic() {
@@ -543,4 +791,103 @@ mod tests {
 
         let cmpID = state.create_native_component(&eID, "test", HashMap::new());
     }
+
+    #[test]
+    fn property_cache_invalidation() {
+        let mut state = EntitiesState::new();
+
+        let entity = state.create_entity(None, true);
+
+        let mut data = HashMap::new();
+        data.insert("property".to_string(), String("health".to_string()));
+        data.insert("value".to_string(), Number(5.0));
+        let cmpID = state.create_native_component(&entity, "property", data).unwrap();
+
+        assert_eq!(state.get_property_value(&entity, "health").unwrap(), Number(5.0));
+        let revision = state.get_property_revision(&entity, "health");
+        assert!(revision.is_some());
+
+        state.set(&cmpID, "value", Number(3.0)).unwrap();
+        assert_eq!(state.get_property_value(&entity, "health").unwrap(), Number(3.0));
+        assert!(state.get_property_revision(&entity, "health") != revision);
+
+        state.set(&cmpID, "property", String("mana".to_string())).unwrap();
+        assert_eq!(state.get_property_value(&entity, "health").unwrap(), Empty);
+        assert_eq!(state.get_property_value(&entity, "mana").unwrap(), Number(3.0));
+
+        state.destroy_component(&cmpID).unwrap();
+        assert_eq!(state.get_property_value(&entity, "mana").unwrap(), Empty);
+    }
+
+    #[test]
+    fn property_revisions_removal() {
+        let mut state = EntitiesState::new();
+
+        let entity = state.create_entity(None, true);
+
+        let mut data = HashMap::new();
+        data.insert("property".to_string(), String("health".to_string()));
+        data.insert("value".to_string(), Number(5.0));
+        let cmpID = state.create_native_component(&entity, "property", data).unwrap();
+        state.set(&cmpID, "value", Number(3.0)).unwrap();
+        assert!(state.property_revisions.len() != 0);
+
+        state.destroy_entity(&entity).unwrap();
+        assert_eq!(state.property_revisions.len(), 0);
+    }
+
+    #[test]
+    fn entity_revisions() {
+        let mut state = EntitiesState::new();
+
+        let prototype = state.create_entity(None, false);
+        let entity = state.create_entity(None, true);
+        let other = state.create_entity(None, true);
+
+        let mut data = HashMap::new();
+        data.insert("x".to_string(), Number(0.0));
+        let cmpID = state.create_native_component(&prototype, "position", data).unwrap();
+        state.create_component_from_entity(&entity, &prototype, HashMap::new()).unwrap();
+
+        let revision = state.get_entity_revision(&entity);
+        let otherRevision = state.get_entity_revision(&other);
+        assert!(revision.is_some());
+
+        // modifying the data of an inherited component changes the revision of the entity
+        state.set(&cmpID, "x", Number(1.0)).unwrap();
+        assert!(state.get_entity_revision(&entity) != revision);
+        assert_eq!(state.get_entity_revision(&other), otherRevision);
+
+        let revision = state.get_entity_revision(&other);
+        let cmpID = state.create_native_component(&other, "position", HashMap::new()).unwrap();
+        assert!(state.get_entity_revision(&other) != revision);
+
+        let revision = state.get_entity_revision(&other);
+        state.destroy_component(&cmpID).unwrap();
+        assert!(state.get_entity_revision(&other) != revision);
+    }
+
+    #[test]
+    fn attached_entities() {
+        let mut state = EntitiesState::new();
+
+        let owner = state.create_entity(None, true);
+        let cmpID = state.create_native_component(&owner, "childEntity", HashMap::new()).unwrap();
+
+        // destroying the component destroys the entity
+        let child = state.create_entity(None, true);
+        state.attach_entity_to_component(&child, &cmpID).unwrap();
+        state.attach_component_to_entity(&cmpID, &child).unwrap();
+        state.destroy_component(&cmpID).unwrap();
+        assert!(state.is_entity_visible(&child).is_err());
+
+        // destroying the entity destroys the component
+        let cmpID = state.create_native_component(&owner, "childEntity", HashMap::new()).unwrap();
+        let child = state.create_entity(None, true);
+        state.attach_entity_to_component(&child, &cmpID).unwrap();
+        state.attach_component_to_entity(&cmpID, &child).unwrap();
+        state.destroy_entity(&child).unwrap();
+        assert!(state.get_owner(&cmpID).is_err());
+        assert!(state.is_entity_visible(&owner).unwrap());
+    }
 }
@@ -0,0 +1,133 @@
+use entities::{ EntitiesHelper, ComponentID };
+use entities::List;
+use std::collections::HashSet;
+use super::{ Message, Pressed, Released };
+
+/// Keeps track of the elements that are down and of the actions that are active.
+pub struct ActionsState {
+    held: HashSet<String>,
+    active: HashSet<String>
+}
+
+impl ActionsState {
+    pub fn new()
+        -> ActionsState
+    {
+        ActionsState {
+            held: HashSet::new(),
+            active: HashSet::new()
+        }
+    }
+
+    /// Processes a message and returns the actions that have been activated (true)
+    ///  or deactivated (false) because of it.
+    /// The bindings are read from the "inputAction" components every time, so that they
+    ///  can be modified while the game is running.
+    pub fn update<E: EntitiesHelper>(&mut self, state: &E, message: &Message)
+        -> Vec<(String, bool)>
+    {
+        match message {
+            &Pressed(ref e) => { self.held.insert(format!("{}", e)); },
+            &Released(ref e) => { self.held.remove(&format!("{}", e)); },
+            _ => return Vec::new()
+        };
+
+        // each action is active if at least one of its bindings is satisfied
+        let mut nowActive = HashSet::new();
+        let mut actions = Vec::new();
+
+        let mut components = state.get_visible_native_components("inputAction");
+        components.sort();
+
+        for cmp in components.iter() {
+            let action = match state.get_as_string(cmp, "action") {
+                Some(a) => a,
+                None => continue
+            };
+
+            if !actions.contains(&action) {
+                actions.push(action.clone());
+            }
+
+            if read_bindings(state, cmp).iter().any(|chord| chord.iter().all(|e| self.held.contains(e))) {
+                nowActive.insert(action);
+            }
+        }
+
+        let mut result = Vec::new();
+        for action in actions.move_iter() {
+            match (self.active.contains(&action), nowActive.contains(&action)) {
+                (false, true) => result.push((action, true)),
+                (true, false) => result.push((action, false)),
+                _ => ()
+            }
+        }
+
+        // actions whose component has disappeared are deactivated too
+        for action in self.active.iter() {
+            if !nowActive.contains(action) && !result.iter().any(|&(ref a, _)| a == action) {
+                result.push((action.clone(), false));
+            }
+        }
+
+        self.active = nowActive;
+        result
+    }
+}
+
+/// Reads the "elements" of an "inputAction" component.
+/// Each binding is either the name of an element, or a list of elements that must be down
+///  at the same time (for example `["LControl", "S"]`).
+fn read_bindings<E: EntitiesHelper>(state: &E, cmp: &ComponentID)
+    -> Vec<Vec<String>>
+{
+    let list = match state.get_and_resolve(cmp, "elements") {
+        Ok(List(list)) => list,
+        _ => return Vec::new()
+    };
+
+    list.iter()
+        .filter_map(|binding| match binding {
+            &::entities::String(ref e) => Some(vec!(e.clone())),
+            &List(ref chord) => Some(chord.iter()
+                .filter_map(|e| match e { &::entities::String(ref e) => Some(e.clone()), _ => None })
+                .collect()),
+            _ => None
+        })
+        .filter(|chord: &Vec<String>| chord.len() != 0)
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use entities::{ EntitiesState, EntitiesHelper, List, String };
+    use super::ActionsState;
+    use super::super::{ Pressed, Released, LControl, S, Space };
+
+    #[test]
+    fn chords() {
+        let mut state = EntitiesState::new();
+        let entity = state.create_entity(None, true);
+
+        let mut data = HashMap::new();
+        data.insert("action".to_string(), String("save".to_string()));
+        data.insert("elements".to_string(), List(vec!(
+            List(vec!(String("LControl".to_string()), String("S".to_string())))
+        )));
+        let cmp = state.create_native_component(&entity, "inputAction", data).unwrap();
+
+        let mut actions = ActionsState::new();
+        assert_eq!(actions.update(&state, &Pressed(S)), vec!());
+        assert_eq!(actions.update(&state, &Released(S)), vec!());
+        assert_eq!(actions.update(&state, &Pressed(LControl)), vec!());
+        assert_eq!(actions.update(&state, &Pressed(S)), vec!(("save".to_string(), true)));
+        assert_eq!(actions.update(&state, &Released(LControl)), vec!(("save".to_string(), false)));
+        actions.update(&state, &Released(S));
+
+        // rebinding
+        state.set(&cmp, "elements", List(vec!(String("Space".to_string())))).unwrap();
+        assert_eq!(actions.update(&state, &Pressed(Space)), vec!(("save".to_string(), true)));
+    }
+}
@@ -0,0 +1,137 @@
+use entities::{ EntitiesState, EntitiesHelper, ComponentID, NativeComponentType };
+use entities::Number;
+use std::collections::HashMap;
+use super::{ Message, AxisMoved };
+use super::routing;
+
+/// Processes all "axisHandler" components.
+pub fn process_axis_handlers(state: &mut EntitiesState, messages: &[Message])
+{
+    let modal = routing::get_modal_entities(state);
+
+    for msg in messages.iter() {
+        let (element, value) = match msg {
+            &AxisMoved(ref e, value) => (format!("{}", e), value),
+            _ => continue
+        };
+
+        for cmp in state
+            .get_visible_native_components("axisHandler")
+            .move_iter()
+            .filter(|c| state.get_as_string(c, "element") == Some(element.clone()) &&
+                        routing::is_receiving(state, c, &modal))
+            .collect::<Vec<ComponentID>>().move_iter()
+        {
+            let value = apply_dead_zone(value, state.get_as_number(&cmp, "deadZone").unwrap_or(0.2)) *
+                state.get_as_number(&cmp, "scale").unwrap_or(1.0);
+
+            update_axis_handler(state, &cmp, value);
+        }
+    }
+}
+
+/// Returns 0 if the value is inside the dead zone, otherwise rescales the value so that
+///  it goes progressively from 0 at the edge of the dead zone to 1.
+fn apply_dead_zone(value: f64, deadZone: f64)
+    -> f64
+{
+    if deadZone >= 1.0 || value.abs() <= deadZone {
+        return 0.0;
+    }
+
+    value.signum() * (value.abs().min(1.0) - deadZone) / (1.0 - deadZone)
+}
+
+/// Writes the value of the axis in the property or the requested movement of the owner.
+fn update_axis_handler(state: &mut EntitiesState, cmp: &ComponentID, value: f64)
+{
+    let owner = match state.get_owner(cmp) {
+        Ok(o) => o,
+        Err(_) => return
+    };
+
+    match state.get_as_string(cmp, "property") {
+        Some(property) => {
+            let components = state
+                .get_visible_native_components("property")
+                .move_iter()
+                .filter(|c| state.get_owner(c).ok() == Some(owner.clone()) &&
+                            state.get_as_string(c, "property") == Some(property.clone()))
+                .collect::<Vec<ComponentID>>();
+
+            if components.len() == 0 {
+                let mut data = HashMap::new();
+                data.insert("property".to_string(), ::entities::String(property));
+                data.insert("value".to_string(), Number(value));
+                let newCmp = state.create_native_component(&owner, "property", data).unwrap();
+                state.set_component_parent(&newCmp, cmp);
+
+            } else {
+                for c in components.iter() {
+                    state.set(c, "value", Number(value)).ok();
+                }
+            }
+        },
+        None => ()
+    };
+
+    match state.get_as_string(cmp, "movement") {
+        Some(field) => {
+            // the requestedMovement is a child of the axisHandler
+            let movement = state.get_component_children(cmp).unwrap_or(Vec::new())
+                .move_iter()
+                .find(|c| match state.get_type(c) {
+                    Ok(NativeComponentType(ref t)) => t.as_slice() == "requestedMovement",
+                    _ => false
+                });
+
+            match movement {
+                Some(c) => { state.set(&c, field.as_slice(), Number(value)).ok(); },
+                None => {
+                    let mut data = HashMap::new();
+                    data.insert(field, Number(value));
+                    let newCmp = state.create_native_component(&owner, "requestedMovement", data).unwrap();
+                    state.set_component_parent(&newCmp, cmp);
+                }
+            };
+        },
+        None => ()
+    };
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use entities::{ EntitiesState, EntitiesHelper, Number, String };
+    use super::{ apply_dead_zone, process_axis_handlers };
+    use super::super::{ AxisMoved, XAxis, YAxis };
+
+    #[test]
+    fn dead_zone() {
+        assert_eq!(apply_dead_zone(0.1, 0.2), 0.0);
+        assert_eq!(apply_dead_zone(-0.2, 0.2), 0.0);
+        assert_eq!(apply_dead_zone(0.75, 0.5), 0.5);
+        assert_eq!(apply_dead_zone(-1.0, 0.2), -1.0);
+    }
+
+    #[test]
+    fn movement() {
+        let mut state = EntitiesState::new();
+        let entity = state.create_entity(None, true);
+
+        let mut data = HashMap::new();
+        data.insert("element".to_string(), String("XAxis".to_string()));
+        data.insert("movement".to_string(), String("x".to_string()));
+        data.insert("scale".to_string(), Number(4.0));
+        data.insert("deadZone".to_string(), Number(0.0));
+        let cmp = state.create_native_component(&entity, "axisHandler", data).unwrap();
+
+        process_axis_handlers(&mut state, [AxisMoved(XAxis, 0.5), AxisMoved(YAxis, 1.0)]);
+        process_axis_handlers(&mut state, [AxisMoved(XAxis, -0.25)]);
+
+        let children = state.get_component_children(&cmp).unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(state.get_as_number(children.get(0), "x"), Some(-1.0));
+    }
+}
@@ -0,0 +1,410 @@
+use entities::{ EntitiesHelper, EntityID, ComponentID };
+use entities::{ List, Number, FromProperty, FromScript };
+use std::collections::{ HashMap, HashSet };
+use std::f64;
+use physics;
+
+/// Size of the cells of the index, in world units.
+static CELL_SIZE: f64 = 4.0;
+
+/// Maximum number of cells that a clickBox can cover before being considered as large.
+static MAX_CELLS_PER_AREA: int = 64;
+
+/// Area of a clickBox, in world coordinates.
+#[deriving(Clone, Show, PartialEq)]
+pub enum ClickShape {
+    Rectangle(f64, f64, f64, f64),      // left, bottom, right, top
+    Circle(f64, f64, f64),              // center and radius
+    Polygon(Vec<(f64, f64)>)
+}
+
+impl ClickShape {
+    /// Returns the rectangle around the shape as (left, bottom, right, top).
+    pub fn get_bounds(&self)
+        -> (f64, f64, f64, f64)
+    {
+        match *self {
+            Rectangle(l, b, r, t) => (l, b, r, t),
+            Circle(x, y, radius) => (x - radius, y - radius, x + radius, y + radius),
+            Polygon(ref points) => points.iter()
+                .fold((f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+                      |(l, b, r, t), &(x, y)| (l.min(x), b.min(y), r.max(x), t.max(y)))
+        }
+    }
+
+    pub fn contains_point(&self, x: f64, y: f64)
+        -> bool
+    {
+        match *self {
+            Rectangle(l, b, r, t) => x >= l && x <= r && y >= b && y <= t,
+            Circle(cx, cy, radius) => (x - cx) * (x - cx) + (y - cy) * (y - cy) <= radius * radius,
+            Polygon(ref points) => {
+                // counting the number of edges crossed by a ray going to the right
+                let mut inside = false;
+                for i in range(0, points.len()) {
+                    let &(x1, y1) = points.get(i);
+                    let &(x2, y2) = points.get((i + 1) % points.len());
+                    if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+        }
+    }
+}
+
+/// A clickBox and the information required to sort it.
+#[deriving(Clone, Show)]
+struct ClickArea {
+    entity: EntityID,
+    component: ComponentID,
+    shape: ClickShape,
+    priority: f64,
+    z: f64,
+    // revisions of the entity and of the properties used when the area was read,
+    //  or None if it must be read every time
+    revisions: Option<Vec<uint>>
+}
+
+impl ClickArea {
+    /// Returns true if this area is drawn above the other one.
+    /// Areas with the highest priority come first, then the ones with the highest z,
+    ///  then the most recent components.
+    fn is_above(&self, other: &ClickArea)
+        -> bool
+    {
+        if self.priority != other.priority { return self.priority > other.priority }
+        if self.z != other.z { return self.z > other.z }
+        self.component > other.component
+    }
+
+    /// Returns the range of cells covered by the area, as (minX, minY, maxX, maxY).
+    fn get_cells(&self)
+        -> (int, int, int, int)
+    {
+        let (l, b, r, t) = self.shape.get_bounds();
+        let (minX, minY) = get_cell(l, b);
+        let (maxX, maxY) = get_cell(r, t);
+        (minX, minY, maxX, maxY)
+    }
+}
+
+/// Spatial index of all the visible clickBoxes.
+/// The world is divided in a grid, and each cell knows which clickBoxes overlap it.
+/// The index is kept between ticks and only the clickBoxes that have changed are
+///  indexed again.
+pub struct ClickBoxIndex {
+    areas: HashMap<ComponentID, ClickArea>,
+    cells: HashMap<(int, int), Vec<ComponentID>>,
+    // areas which cover too many cells to be put in the grid, and which are always tested
+    large: Vec<ComponentID>
+}
+
+impl ClickBoxIndex {
+    pub fn new()
+        -> ClickBoxIndex
+    {
+        ClickBoxIndex { areas: HashMap::new(), cells: HashMap::new(), large: Vec::new() }
+    }
+
+    /// Builds the index from the current content of the state.
+    pub fn from_state<E: EntitiesHelper>(state: &E)
+        -> ClickBoxIndex
+    {
+        let mut index = ClickBoxIndex::new();
+        index.update(state);
+        index
+    }
+
+    /// Updates the index so that it matches the current content of the state.
+    /// Only the clickBoxes whose entity has had a component created, destroyed or modified
+    ///  since the last update are read again and moved in the grid.
+    pub fn update<E: EntitiesHelper>(&mut self, state: &E)
+    {
+        let components = state.get_visible_native_components("clickBox");
+
+        // removing the areas whose component has disappeared
+        {
+            let current: HashSet<&ComponentID> = components.iter().collect();
+            let toRemove = self.areas.keys()
+                .filter(|c| !current.contains(c))
+                .map(|c| c.clone())
+                .collect::<Vec<ComponentID>>();
+
+            for c in toRemove.iter() {
+                self.remove(c);
+            }
+        }
+
+        for cmp in components.move_iter() {
+            let entity = match state.get_owner(&cmp) { Ok(e) => e, Err(_) => continue };
+            let revisions = get_revisions(state, &cmp, &entity);
+
+            match self.areas.find(&cmp) {
+                Some(a) if a.entity == entity && a.revisions.is_some() && a.revisions == revisions => continue,
+                _ => ()
+            };
+
+            self.remove(&cmp);
+
+            let position = physics::get_entity_position(state, &entity);
+            let shape = match read_shape(state, &cmp, position.x as f64, position.y as f64) {
+                Some(s) => s,
+                None => continue
+            };
+
+            self.insert(ClickArea {
+                entity: entity,
+                component: cmp.clone(),
+                shape: shape,
+                priority: state.get_as_number(&cmp, "priority").unwrap_or(0.0),
+                z: position.z as f64,
+                revisions: revisions
+            });
+        }
+    }
+
+    /// Returns the entity of the top-most clickBox that contains the point.
+    pub fn find_at(&self, x: f64, y: f64)
+        -> Option<EntityID>
+    {
+        let cell = get_cell(x, y);
+
+        let candidates = match self.cells.find(&cell) {
+            Some(c) => c.iter().chain(self.large.iter()).collect::<Vec<&ComponentID>>(),
+            None => self.large.iter().collect()
+        };
+
+        candidates.move_iter()
+            .map(|c| self.areas.find(c).unwrap())
+            .filter(|a| a.shape.contains_point(x, y))
+            .fold(None, |top: Option<&ClickArea>, a| match top {
+                Some(t) if !a.is_above(t) => Some(t),
+                _ => Some(a)
+            })
+            .map(|a| a.entity.clone())
+    }
+
+    fn insert(&mut self, area: ClickArea)
+    {
+        let (minX, minY, maxX, maxY) = area.get_cells();
+
+        if (maxX - minX + 1) * (maxY - minY + 1) > MAX_CELLS_PER_AREA {
+            self.large.push(area.component.clone());
+
+        } else {
+            for y in range(minY, maxY + 1) {
+                for x in range(minX, maxX + 1) {
+                    self.cells.find_or_insert((x, y), Vec::new()).push(area.component.clone());
+                }
+            }
+        }
+
+        self.areas.insert(area.component.clone(), area);
+    }
+
+    fn remove(&mut self, component: &ComponentID)
+    {
+        let area = match self.areas.pop(component) { Some(a) => a, None => return };
+        let (minX, minY, maxX, maxY) = area.get_cells();
+
+        if (maxX - minX + 1) * (maxY - minY + 1) > MAX_CELLS_PER_AREA {
+            self.large.retain(|c| c != component);
+            return;
+        }
+
+        for y in range(minY, maxY + 1) {
+            for x in range(minX, maxX + 1) {
+                let empty = match self.cells.find_mut(&(x, y)) {
+                    Some(list) => { list.retain(|c| c != component); list.len() == 0 },
+                    None => false
+                };
+
+                if empty {
+                    self.cells.remove(&(x, y));
+                }
+            }
+        }
+    }
+}
+
+/// Returns the shapes of all the visible clickBoxes, in world coordinates,
+///  along with their component and their owner.
+pub fn get_click_shapes<E: EntitiesHelper>(state: &E)
+    -> Vec<(ComponentID, EntityID, ClickShape)>
+{
+    let mut components = state.get_visible_native_components("clickBox");
+    components.sort();
+
+    components.move_iter()
+        .filter_map(|cmp| {
+            let entity = match state.get_owner(&cmp) { Ok(e) => e, Err(_) => return None };
+            let position = physics::get_entity_position(state, &entity);
+            read_shape(state, &cmp, position.x as f64, position.y as f64).map(|s| (cmp.clone(), entity, s))
+        })
+        .collect()
+}
+
+fn get_cell(x: f64, y: f64)
+    -> (int, int)
+{
+    ((x / CELL_SIZE).floor() as int, (y / CELL_SIZE).floor() as int)
+}
+
+/// Returns the revision of the entity followed by the revisions of the properties
+///  used by the clickBox. The area must be read again when they change.
+/// Returns None if a field comes from a script, in which case the area must be read every time.
+fn get_revisions<E: EntitiesHelper>(state: &E, cmp: &ComponentID, entity: &EntityID)
+    -> Option<Vec<uint>>
+{
+    static FIELDS: &'static [&'static str] = &["shape", "leftX", "bottomY", "rightX", "topY",
+        "centerX", "centerY", "radius", "points", "priority"];
+
+    let mut revisions = vec!(match state.get_entity_revision(entity) { Some(r) => r, None => return None });
+
+    for field in FIELDS.iter() {
+        match state.get(cmp, *field) {
+            Ok(&FromScript(_)) => return None,
+            Ok(&FromProperty(ref p)) => match state.get_property_revision(entity, p.as_slice()) {
+                Some(r) => revisions.push(r),
+                None => return None
+            },
+            _ => ()
+        }
+    }
+
+    Some(revisions)
+}
+
+/// Reads the shape of a clickBox, and adds the position of the entity to it.
+fn read_shape<E: EntitiesHelper>(state: &E, cmp: &ComponentID, x: f64, y: f64)
+    -> Option<ClickShape>
+{
+    match state.get_as_string(cmp, "shape").unwrap_or("rectangle".to_string()).as_slice() {
+        "rectangle" =>
+            match (state.get_as_number(cmp, "leftX"), state.get_as_number(cmp, "bottomY"),
+                   state.get_as_number(cmp, "rightX"), state.get_as_number(cmp, "topY"))
+            {
+                (Some(l), Some(b), Some(r), Some(t)) =>
+                    Some(Rectangle(x + l.min(r), y + b.min(t), x + l.max(r), y + b.max(t))),
+                _ => None
+            },
+
+        "circle" =>
+            state.get_as_number(cmp, "radius").map(|radius| Circle(
+                x + state.get_as_number(cmp, "centerX").unwrap_or(0.0),
+                y + state.get_as_number(cmp, "centerY").unwrap_or(0.0),
+                radius
+            )),
+
+        "polygon" => {
+            let points: Vec<(f64, f64)> = match state.get_and_resolve(cmp, "points") {
+                Ok(List(list)) => list.iter()
+                    .filter_map(|p| match p {
+                        &List(ref c) if c.len() >= 2 => match (c.get(0), c.get(1)) {
+                            (&Number(px), &Number(py)) => Some((x + px, y + py)),
+                            _ => None
+                        },
+                        _ => None
+                    })
+                    .collect(),
+                _ => return None
+            };
+
+            if points.len() >= 3 { Some(Polygon(points)) } else { None }
+        },
+
+        _ => None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use entities::{ EntitiesState, EntitiesHelper, List, Number, String };
+    use super::ClickBoxIndex;
+
+    #[test]
+    fn priority() {
+        let mut state = EntitiesState::new();
+        let background = state.create_entity(None, true);
+        let button = state.create_entity(None, true);
+
+        let mut data = HashMap::new();
+        data.insert("leftX".to_string(), Number(-100.0));
+        data.insert("rightX".to_string(), Number(100.0));
+        data.insert("bottomY".to_string(), Number(-100.0));
+        data.insert("topY".to_string(), Number(100.0));
+        data.insert("priority".to_string(), Number(-1.0));
+        state.create_native_component(&background, "clickBox", data).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("shape".to_string(), String("circle".to_string()));
+        data.insert("radius".to_string(), Number(1.0));
+        state.create_native_component(&button, "clickBox", data).unwrap();
+
+        let index = ClickBoxIndex::from_state(&state);
+        assert_eq!(index.find_at(0.5, 0.5), Some(button.clone()));
+        assert_eq!(index.find_at(0.9, 0.9), Some(background.clone()));
+        assert_eq!(index.find_at(50.0, -50.0), Some(background.clone()));
+        assert_eq!(index.find_at(150.0, 0.0), None);
+    }
+
+    #[test]
+    fn polygon() {
+        let mut state = EntitiesState::new();
+        let entity = state.create_entity(None, true);
+
+        // a square rotated by 45 degrees
+        let mut data = HashMap::new();
+        data.insert("shape".to_string(), String("polygon".to_string()));
+        data.insert("points".to_string(), List(vec!(
+            List(vec!(Number(0.0), Number(-1.0))),
+            List(vec!(Number(1.0), Number(0.0))),
+            List(vec!(Number(0.0), Number(1.0))),
+            List(vec!(Number(-1.0), Number(0.0)))
+        )));
+        state.create_native_component(&entity, "clickBox", data).unwrap();
+
+        let index = ClickBoxIndex::from_state(&state);
+        assert_eq!(index.find_at(0.2, 0.2), Some(entity.clone()));
+        assert_eq!(index.find_at(0.8, 0.8), None);
+    }
+
+    #[test]
+    fn update() {
+        let mut state = EntitiesState::new();
+        let entity = state.create_entity(None, true);
+
+        let mut data = HashMap::new();
+        data.insert("x".to_string(), Number(0.0));
+        data.insert("y".to_string(), Number(0.0));
+        let position = state.create_native_component(&entity, "position", data).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("shape".to_string(), String("circle".to_string()));
+        data.insert("radius".to_string(), Number(1.0));
+        let clickBox = state.create_native_component(&entity, "clickBox", data).unwrap();
+
+        let mut index = ClickBoxIndex::new();
+        index.update(&state);
+        assert_eq!(index.find_at(0.0, 0.0), Some(entity.clone()));
+
+        // moving the entity
+        state.set(&position, "x", Number(10.0)).unwrap();
+        index.update(&state);
+        assert_eq!(index.find_at(0.0, 0.0), None);
+        assert_eq!(index.find_at(10.0, 0.0), Some(entity.clone()));
+
+        // a box larger than the grid
+        state.set(&clickBox, "radius", Number(1000.0)).unwrap();
+        index.update(&state);
+        assert_eq!(index.find_at(-500.0, 0.0), Some(entity.clone()));
+
+        state.destroy_component(&clickBox).unwrap();
+        index.update(&state);
+        assert_eq!(index.find_at(10.0, 0.0), None);
+    }
+}
@@ -1,8 +1,14 @@
+pub use self::pressed::PressedElements;
 pub use self::system::InputSystem;
 
+mod action;
+mod axis;
+pub mod click_box;
+mod pressed;
+mod routing;
 mod system;
 
-#[deriving(Show, Clone)]
+#[deriving(Show, Clone, PartialEq, Eq, Hash)]
 pub enum Element {
     XAxis,
     YAxis,
@@ -10,6 +16,8 @@ pub enum Element {
     Slider1,
     Slider2,
     Slider3,
+    WheelX,
+    WheelY,
     Button0,
     Button1,
     Button2,
@@ -178,5 +186,6 @@ pub enum Message {
     Pressed(Element),
     Released(Element),
     Text(char),
-    MouseMoved(f64, f64)        // x and y coords between -1 and 1
+    MouseMoved(f64, f64),       // x and y coords between -1 and 1
+    AxisMoved(Element, f64)
 }
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+/// Keeps track of the elements and actions that are down and of how long they have been.
+pub struct PressedElements {
+    durations: HashMap<String, f64>
+}
+
+impl PressedElements {
+    pub fn new()
+        -> PressedElements
+    {
+        PressedElements {
+            durations: HashMap::new()
+        }
+    }
+
+    pub fn press(&mut self, name: &str)
+    {
+        self.durations.find_or_insert(name.to_string(), 0.0);
+    }
+
+    pub fn release(&mut self, name: &str)
+    {
+        self.durations.remove(&name.to_string());
+    }
+
+    /// Adds the elapsed time to the duration of everything that is down.
+    pub fn advance(&mut self, elapsed: f64)
+    {
+        for (_, duration) in self.durations.mut_iter() {
+            *duration += elapsed;
+        }
+    }
+
+    pub fn is_pressed(&self, name: &str)
+        -> bool
+    {
+        self.durations.contains_key_equiv(&name)
+    }
+
+    /// Returns the number of seconds since the element or action has been pressed,
+    ///  or None if it is not down.
+    pub fn pressed_duration(&self, name: &str)
+        -> Option<f64>
+    {
+        self.durations.find_equiv(&name).map(|d| *d)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::PressedElements;
+
+    #[test]
+    fn durations() {
+        let mut pressed = PressedElements::new();
+        pressed.press("A");
+        pressed.advance(0.5);
+        pressed.press("B");
+        pressed.press("A");
+        pressed.advance(0.25);
+
+        assert_eq!(pressed.pressed_duration("A"), Some(0.75));
+        assert_eq!(pressed.pressed_duration("B"), Some(0.25));
+        assert!(!pressed.is_pressed("C"));
+
+        pressed.release("A");
+        assert!(!pressed.is_pressed("A"));
+    }
+}
@@ -0,0 +1,117 @@
+use entities::{ EntitiesHelper, EntityID, ComponentID };
+use entities::{ Entity, List };
+
+/// Returns the entities that receive the input because of the top-most visible "inputModal"
+///  component, or None if there is no such component and all entities receive the input.
+pub fn get_modal_entities<E: EntitiesHelper>(state: &E)
+    -> Option<Vec<EntityID>>
+{
+    let modal = state
+        .get_visible_native_components("inputModal")
+        .move_iter()
+        .fold(None, |top: Option<(ComponentID, f64)>, c| {
+            let priority = state.get_as_number(&c, "priority").unwrap_or(0.0);
+            match top {
+                Some((t, p)) if p > priority || (p == priority && t > c) => Some((t, p)),
+                _ => Some((c, priority))
+            }
+        });
+
+    let (cmp, _) = match modal {
+        Some(m) => m,
+        None => return None
+    };
+
+    let mut entities = match state.get_owner(&cmp) {
+        Ok(o) => vec!(o),
+        Err(_) => Vec::new()
+    };
+
+    match state.get_and_resolve(&cmp, "entities") {
+        Ok(List(list)) => for e in list.move_iter() {
+            match e { Entity(e) => entities.push(e), _ => () }
+        },
+        _ => ()
+    };
+
+    Some(entities)
+}
+
+/// Returns true if the entity can receive the input, given the result of `get_modal_entities`.
+pub fn is_receiving<E: EntitiesHelper>(state: &E, cmp: &ComponentID, modal: &Option<Vec<EntityID>>)
+    -> bool
+{
+    match *modal {
+        None => true,
+        Some(ref entities) => match state.get_owner(cmp) {
+            Ok(owner) => entities.contains(&owner),
+            Err(_) => false
+        }
+    }
+}
+
+/// Filters and orders the handlers that match the same event.
+/// The handlers with the highest "priority" come first, then the most recent ones.
+/// A handler whose "consume" element is true stops the propagation to the next handlers.
+pub fn route<E: EntitiesHelper>(state: &E, mut handlers: Vec<ComponentID>, modal: &Option<Vec<EntityID>>)
+    -> Vec<ComponentID>
+{
+    handlers.retain(|c| is_receiving(state, c, modal));
+
+    handlers.sort_by(|c1, c2| {
+        let p1 = state.get_as_number(c1, "priority").unwrap_or(0.0);
+        let p2 = state.get_as_number(c2, "priority").unwrap_or(0.0);
+        match p2.partial_cmp(&p1) {
+            Some(Equal) | None => c2.cmp(c1),
+            Some(o) => o
+        }
+    });
+
+    match handlers.iter().position(|c| state.get_as_boolean(c, "consume").unwrap_or(false)) {
+        Some(pos) => handlers.truncate(pos + 1),
+        None => ()
+    };
+
+    handlers
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use entities::{ EntitiesState, EntitiesHelper, Boolean, Entity, List, Number };
+    use super::{ get_modal_entities, route };
+
+    #[test]
+    fn consume_and_modal() {
+        let mut state = EntitiesState::new();
+        let game = state.create_entity(None, true);
+        let menu = state.create_entity(None, true);
+        let button = state.create_entity(None, true);
+
+        let gameHandler = state.create_native_component(&game, "inputHandler", HashMap::new()).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("priority".to_string(), Number(10.0));
+        data.insert("consume".to_string(), Boolean(true));
+        let menuHandler = state.create_native_component(&menu, "inputHandler", data).unwrap();
+
+        let buttonHandler = state.create_native_component(&button, "inputHandler", HashMap::new()).unwrap();
+
+        let handlers = vec!(gameHandler.clone(), menuHandler.clone(), buttonHandler.clone());
+        assert_eq!(route(&state, handlers.clone(), &None), vec!(menuHandler.clone()));
+
+        state.set(&menuHandler, "consume", Boolean(false)).unwrap();
+        assert_eq!(route(&state, handlers.clone(), &None),
+                   vec!(menuHandler.clone(), buttonHandler.clone(), gameHandler.clone()));
+
+        // the menu captures the input, except for its button
+        assert_eq!(get_modal_entities(&state), None);
+        let mut data = HashMap::new();
+        data.insert("entities".to_string(), List(vec!(Entity(button.clone()))));
+        state.create_native_component(&menu, "inputModal", data).unwrap();
+
+        let modal = get_modal_entities(&state);
+        assert_eq!(route(&state, handlers.clone(), &modal), vec!(menuHandler.clone(), buttonHandler.clone()));
+    }
+}
@@ -1,13 +1,23 @@
-use super::{ Message, Element, Pressed, Released, MouseMoved };
+use super::{ Message, Element, Pressed, Released, MouseMoved, AxisMoved, Text };
+use super::action::ActionsState;
+use super::axis;
+use super::pressed::PressedElements;
+use super::routing;
+use super::click_box::ClickBoxIndex;
 use entities::{ EntitiesState, EntitiesHelper, EntityID, ComponentID, NativeComponentType };
+use entities::{ Data, Number };
 use nalgebra::na;
-use nalgebra::na::Inv;
+use std::collections::HashMap;
 use script;
 use log;
 
 pub struct InputSystem {
     current_hover: Option<EntityID>,
-    last_known_mouse_position: na::Vec2<f64>
+    last_known_mouse_position: na::Vec2<f64>,
+    actions: ActionsState,
+    pressed: PressedElements,
+    clickBoxes: ClickBoxIndex,
+    windowSize: (uint, uint)
 }
 
 impl InputSystem {
@@ -16,15 +26,42 @@ impl InputSystem {
     {
         InputSystem {
             current_hover: None,
-            last_known_mouse_position: na::Vec2::new(0.0, 0.0)
+            last_known_mouse_position: na::Vec2::new(0.0, 0.0),
+            actions: ActionsState::new(),
+            pressed: PressedElements::new(),
+            clickBoxes: ClickBoxIndex::new(),
+            windowSize: (1, 1)
         }
     }
 
+    /// Must be called when the window is resized, as the camera depends on its aspect ratio.
+    pub fn set_window_size(&mut self, width: uint, height: uint)
+    {
+        self.windowSize = (width, height);
+    }
+
     pub fn process(&mut self, state: &mut EntitiesState,
                    elapsed: &f64, messages: &[Message], log: |log::LogRecord|)
     {
         self.process_hover(state, elapsed, messages, |l| log(l));
+        self.process_click_handlers(state, messages, |l| log(l));
         self.process_input_handlers(state, elapsed, messages, |l| log(l));
+        axis::process_axis_handlers(state, messages);
+    }
+
+    /// Returns true if an element or an action is currently down.
+    pub fn is_pressed(&self, name: &str)
+        -> bool
+    {
+        self.pressed.is_pressed(name)
+    }
+
+    /// Returns the number of seconds since an element or an action has been pressed,
+    ///  or None if it is not down.
+    pub fn pressed_duration(&self, name: &str)
+        -> Option<f64>
+    {
+        self.pressed.pressed_duration(name)
     }
 
     /// Processes all "inputHandler" components.
@@ -32,33 +69,151 @@ impl InputSystem {
                                   elapsed: &f64, messages: &[Message],
                                   log: |log::LogRecord|)
     {
-        // creating an iterator to have only the "pressed" and "release" messages
-        let mut filteredMessagesIter = messages
-            .iter()
-            .filter_map(|msg| match msg {
-                &Pressed(ref e) => Some((e, true)),
-                &Released(ref e) => Some((e, false)),
-                _ => None
-            });
+        // building the list of the events that concern input handlers, along with the name
+        //  of the element ("element") or action ("action") that they match, their pressed state
+        //  and their value
+        let mut events = Vec::new();
+
+        for msg in messages.iter() {
+            match msg {
+                &Pressed(ref e) => events.push(("element", format!("{}", e), Some(true), None)),
+                &Released(ref e) => events.push(("element", format!("{}", e), Some(false), None)),
+                &AxisMoved(ref e, value) => events.push(("element", format!("{}", e), None, Some(Number(value)))),
+                &Text(c) => events.push(("element", "Text".to_string(), None, Some(::entities::String(c.to_string())))),
+                _ => ()
+            };
+
+            for (action, active) in self.actions.update(state, msg).move_iter() {
+                events.push(("action", action, Some(active), None));
+            }
+        }
+
+        // updating the pressed state before executing any script, so that the scripts
+        //  see the state at the end of this tick
+        self.pressed.advance(*elapsed);
+        for &(_, ref name, pressed, _) in events.iter() {
+            match pressed {
+                Some(true) => self.pressed.press(name.as_slice()),
+                Some(false) => self.pressed.release(name.as_slice()),
+                None => ()
+            }
+        }
+
+        let modal = routing::get_modal_entities(state);
 
-        for (element, pressed) in filteredMessagesIter {
-            let element = format!("{}", element);
+        for (key, name, pressed, value) in events.move_iter() {
 
-            // getting all "inputHandler" components that match the element
-            for component in state
+            // getting all "inputHandler" components that match the element or action
+            let handlers = state
                 .get_visible_native_components("inputHandler")
                 .move_iter()
                 .filter(|c|
-                    match state.get_as_string(c, "element") {
-                        Some(s) => s == element,
+                    match state.get_as_string(c, key) {
+                        Some(s) => s == name,
                         _ => false
                     }
                 )
-                .collect::<Vec<ComponentID>>().move_iter()
-            {
+                .collect::<Vec<ComponentID>>();
+
+            // keeping only the ones that receive the event
+            let routed = routing::route(state, handlers.clone(), &modal);
+
+            for component in routed.iter() {
                 // this component **may** need an update
                 // we delegate this to a subfunction
-                self.update_input_handler(state, &component, pressed, |l| log(l))
+                self.update_input_handler(state, component, pressed, value.clone(), |l| log(l))
+            }
+
+            // the prototypes of the other handlers are removed anyway, otherwise they would stay
+            //  forever if a modal entity appears while the element is down
+            if pressed == Some(false) {
+                let notRouted = handlers.iter()
+                    .filter(|c| !routed.contains(*c) && state.get_as_entity(*c, "prototypeWhilePressed").is_some())
+                    .map(|c| c.clone())
+                    .collect::<Vec<ComponentID>>();
+
+                for component in notRouted.iter() {
+                    for c in state.get_component_children(component).unwrap_or(Vec::new()).move_iter() {
+                        state.destroy_component(&c).ok();
+                    }
+                }
+            }
+        }
+
+        // grouping the handlers whose element or action is down by the name of the element or action
+        let mut held: HashMap<String, Vec<ComponentID>> = HashMap::new();
+        for component in state.get_visible_native_components("inputHandler").move_iter() {
+            match state.get_as_string(&component, "element").or_else(|| state.get_as_string(&component, "action")) {
+                Some(name) if self.pressed.is_pressed(name.as_slice()) =>
+                    held.find_or_insert(name, Vec::new()).push(component),
+                _ => ()
+            }
+        }
+
+        // executing the "scriptWhileHeld" of the handlers that receive the element or action
+        for (_, handlers) in held.move_iter() {
+            for component in routing::route(state, handlers, &modal).move_iter() {
+                match state.get_as_string(&component, "scriptWhileHeld") {
+                    Some(script) => { script::execute_mut(state, Some(&self.pressed), &component, &script.as_slice()).unwrap(); },
+                    None => ()
+                };
+            }
+        }
+    }
+
+    /// Processes all "clickHandler" components.
+    /// Must be called after `process_hover`, as only the entity being hovered can be clicked.
+    fn process_click_handlers(&mut self, state: &mut EntitiesState, messages: &[Message], log: |log::LogRecord|)
+    {
+        for msg in messages.iter() {
+            let (button, pressed) = match msg {
+                &Pressed(ref e) => (format!("{}", e), true),
+                &Released(ref e) => (format!("{}", e), false),
+                _ => continue
+            };
+
+            let components = state
+                .get_visible_native_components("clickHandler")
+                .move_iter()
+                .filter(|c| state.get_as_string(c, "button").unwrap_or("Button0".to_string()) == button)
+                .collect::<Vec<ComponentID>>();
+
+            // releasing the button anywhere removes the prototypes
+            if !pressed {
+                for cmp in components.iter() {
+                    for c in state.get_component_children(cmp).unwrap_or(Vec::new()).move_iter() {
+                        state.destroy_component(&c).ok();
+                    }
+                }
+                continue;
+            }
+
+            let hovered = match self.current_hover {
+                Some(ref e) => e.clone(),
+                None => continue
+            };
+
+            // only the modal entities can be clicked
+            let modal = routing::get_modal_entities(state);
+
+            for cmp in components.move_iter().filter(|c| state.get_owner(c).ok() == Some(hovered.clone()) &&
+                                                         routing::is_receiving(state, c, &modal))
+            {
+                // adding prototype
+                match state.get_as_entity(&cmp, "prototypeWhilePressed") {
+                    Some(prototype) if state.get_component_children(&cmp).unwrap_or(Vec::new()).len() == 0 => {
+                        let newCmp = state.create_component_from_entity(&hovered, &prototype,
+                            ::std::collections::HashMap::new()).unwrap();
+                        state.set_component_parent(&newCmp, &cmp);
+                    },
+                    _ => ()
+                };
+
+                // executing onClick script
+                match state.get_as_string(&cmp, "scriptOnClick") {
+                    Some(script) => { script::execute_mut(state, Some(&self.pressed), &cmp, &script.as_slice()).unwrap(); },
+                    None => ()
+                };
             }
         }
     }
@@ -77,53 +232,21 @@ impl InputSystem {
 
         self.last_known_mouse_position = mouse_position;
 
-        // getting the mouse position in camera units
-        let mouse_position = {
-            let mousePosVector =
-                na::Vec4::new(mouse_position.x as f32, mouse_position.y as f32, 0.0, 1.0);
-            let matrix =
-                { let mut m = ::display::DisplaySystem::get_camera(state).unwrap(); m.inv(); m };
-            let result = mousePosVector * matrix;
-            na::Vec2::new(result.x / result.w, result.y / result.w)
-        };
+        // getting the mouse position in world units
+        // the entity being hovered is also the one that receives the clicks
+        let camera = ::display::DisplaySystem::get_camera(state, self.windowSize)
+            .unwrap_or_else(|| na::Eye::new_identity(4));
+        let world_position = ::display::screen_to_world(&camera, mouse_position.x, mouse_position.y);
 
         // getting which entity is being hovered
-        let hovered_entity: Option<EntityID> = state
-            .get_visible_native_components("clickBox")
-            .move_iter()
-            .filter_map(|component| {
-                let entity = match state.get_owner(&component) { Ok(t) => t, _ => return None };
-                let entity_position =
-                    ::physics::get_entity_position(state, &entity);
-
-                let coord1 = match (state.get_as_number(&component, "leftX"),
-                                    state.get_as_number(&component, "bottomY"))
-                {
-                    (Some(x), Some(y)) =>
-                        na::Vec2::new(x as f32 + entity_position.x, y as f32 + entity_position.y),
-                    _ => return None
-                };
-
-                let coord2 = match (state.get_as_number(&component, "rightX"),
-                                    state.get_as_number(&component, "topY"))
-                {
-                    (Some(x), Some(y)) =>
-                        na::Vec2::new(x as f32 + entity_position.x, y as f32 + entity_position.y),
-                    _ => return None
-                };
-
-                if (coord1.x < coord2.x) && coord2.x < mouse_position.x { return None }
-                if (coord2.x < coord1.x) && coord1.x < mouse_position.x { return None }
-                if (coord1.x > coord2.x) && coord2.x > mouse_position.x { return None }
-                if (coord2.x > coord1.x) && coord1.x > mouse_position.x { return None }
-                if (coord1.y < coord2.y) && coord2.y < mouse_position.y { return None }
-                if (coord2.y < coord1.y) && coord1.y < mouse_position.y { return None }
-                if (coord1.y > coord2.y) && coord2.y > mouse_position.y { return None }
-                if (coord2.y > coord1.y) && coord1.y > mouse_position.y { return None }
-
-                Some(entity)
-            })
-            .next();
+        self.clickBoxes.update(state);
+        let hovered_entity: Option<EntityID> = match world_position {
+            Some((x, y)) => self.clickBoxes.find_at(x, y),
+            None => {
+                log(log::LogRecord::new(log::Warning, format!("the matrix of the camera can't be inverted")));
+                None
+            }
+        };
 
         // if the hovered entity has not changed, we have finished
         if hovered_entity == self.current_hover {
@@ -147,7 +270,7 @@ impl InputSystem {
                 match state.get_as_string(&cmp, "scriptOnLeave") {
                     None => (),
                     Some(script) => {
-                        script::execute_mut(state, &cmp, &script.as_slice()).unwrap();
+                        script::execute_mut(state, Some(&self.pressed), &cmp, &script.as_slice()).unwrap();
                     }
                 };
             }
@@ -185,7 +308,7 @@ impl InputSystem {
                     match state.get_as_string(&cmp, "scriptOnEnter") {
                         None => (),
                         Some(script) =>
-                            { script::execute_mut(state, &cmp, &script.as_slice()).unwrap(); }
+                            { script::execute_mut(state, Some(&self.pressed), &cmp, &script.as_slice()).unwrap(); }
                     };
                 }
             }
@@ -195,13 +318,32 @@ impl InputSystem {
     /// Updates a single "inputHandler" component.
     /// This function checks whether the component has its state matching
     /// the pressed/release state of the input.
+    /// For axes and text, `pressed` is None and the value is written in the "value" element of the component.
     fn update_input_handler(&mut self, state: &mut EntitiesState,
-                            component: &ComponentID, pressed: bool, log: |log::LogRecord|)
+                            component: &ComponentID, pressed: Option<bool>, value: Option<Data>,
+                            log: |log::LogRecord|)
     {
+        match value {
+            Some(v) => { state.set(component, "value", v).ok(); },
+            None => ()
+        };
+
         // executing the script if the component has one
         match state.get_as_string(component, "script") {
             Some(script) => {
-                script::execute_mut(state, component, &script.as_slice()).unwrap();
+                script::execute_mut(state, Some(&self.pressed), component, &script.as_slice()).unwrap();
+            },
+            None => ()
+        };
+
+        let pressed = match pressed {
+            Some(p) => p,
+            None => return
+        };
+
+        match state.get_as_string(component, if pressed { "scriptOnPress" } else { "scriptOnRelease" }) {
+            Some(script) => {
+                script::execute_mut(state, Some(&self.pressed), component, &script.as_slice()).unwrap();
             },
             None => ()
         };
@@ -1,3 +1,3 @@
-pub use self::system::MechanicsSystem;
+pub use self::system::{ MechanicsSystem, get_time_modifier };
 
 mod system;
@@ -0,0 +1,72 @@
+use entities::{ EntitiesState, EntitiesHelper, EntityID, ComponentID };
+use std::collections::HashMap;
+use log;
+
+pub struct ChildEntitySystem {
+    // entity currently linked to each "childEntity" component
+    links: HashMap<ComponentID, EntityID>
+}
+
+impl ChildEntitySystem {
+    pub fn new(_: &EntitiesState, log: |log::LogRecord|)
+        -> ChildEntitySystem
+    {
+        ChildEntitySystem {
+            links: HashMap::new()
+        }
+    }
+
+    pub fn process(&mut self, state: &mut EntitiesState, log: |log::LogRecord|)
+    {
+        let listOfComponents = state.get_visible_native_components("childEntity");
+
+        // forgetting the components that have disappeared
+        // the state has already destroyed the entities linked to them
+        {
+            let toRemove = self.links.keys()
+                .filter(|c| !listOfComponents.contains(*c))
+                .map(|c| c.clone())
+                .collect::<Vec<ComponentID>>();
+
+            for c in toRemove.move_iter() {
+                self.links.remove(&c);
+            }
+        }
+
+        for cmp in listOfComponents.move_iter() {
+            let entity = state.get_as_entity(&cmp, "entity");
+            let current = self.links.find(&cmp).map(|e| e.clone());
+
+            if entity == current {
+                continue;
+            }
+
+            // the "entity" element has changed, breaking the previous link
+            match current {
+                Some(previous) => {
+                    state.detach_entity_from_component(&previous, &cmp).ok();
+                    state.detach_component_from_entity(&cmp, &previous).ok();
+                    self.links.remove(&cmp);
+                },
+                None => ()
+            };
+
+            let entity = match entity {
+                Some(e) => e,
+                None => {
+                    log(log::LogRecord::new(log::Warning, format!("childEntity component {} has no valid \"entity\" element", cmp)));
+                    continue
+                }
+            };
+
+            // if the entity no longer exists, the component must be destroyed with it
+            if state.attach_component_to_entity(&cmp, &entity).is_err() {
+                state.destroy_component(&cmp).ok();
+                continue;
+            }
+
+            state.attach_entity_to_component(&entity, &cmp).unwrap();
+            self.links.insert(cmp, entity);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use entities::{ EntitiesState, EntitiesHelper, ComponentID };
+use entities::{ List, Number };
+use std::collections::HashMap;
+use navigation::NavigationGrid;
+use physics;
+use script;
+use log;
+
+pub struct DestinationSystem {
+    destinations: HashMap<ComponentID, DestinationState>
+}
+
+struct DestinationState {
+    // list of points to go through, the last one being the destination
+    path: Vec<(f64, f64)>,
+    // points that the entity actually follows, either path or the result of the pathfinding
+    route: Vec<(f64, f64)>,
+    // index in route of the point we are currently moving to
+    nextWaypoint: uint,
+    arrived: bool,
+    // "requestedMovement" component created as a child of the "destination" component
+    movement: Option<ComponentID>
+}
+
+impl DestinationSystem {
+    pub fn new(_: &EntitiesState, log: |log::LogRecord|)
+        -> DestinationSystem
+    {
+        DestinationSystem {
+            destinations: HashMap::new()
+        }
+    }
+
+    pub fn process(&mut self, state: &mut EntitiesState, elapsed: &f64, log: |log::LogRecord|)
+    {
+        let listOfComponents = state.get_visible_native_components("destination");
+
+        // forgetting the components that have disappeared
+        // their "requestedMovement" children have been destroyed with them
+        {
+            let toRemove = self.destinations.keys()
+                .filter(|c| !listOfComponents.contains(*c))
+                .map(|c| c.clone())
+                .collect::<Vec<ComponentID>>();
+
+            for c in toRemove.move_iter() {
+                self.destinations.remove(&c);
+            }
+        }
+
+        for cmp in listOfComponents.move_iter() {
+            // the component may have been destroyed by a script in the meantime
+            let owner = match state.get_owner(&cmp) { Ok(o) => o, Err(_) => continue };
+
+            let path = read_path(state, &cmp);
+            if path.len() == 0 {
+                log(log::LogRecord::new(log::Warning, format!("destination component {} has neither a destination nor waypoints", cmp)));
+                continue
+            }
+
+            let destination = self.destinations.find_or_insert(cmp.clone(), DestinationState {
+                path: Vec::new(),
+                route: Vec::new(),
+                nextWaypoint: 0,
+                arrived: false,
+                movement: None
+            });
+
+            let position = physics::get_entity_position(state, &owner);
+            let (posX, posY) = (position.x as f64, position.y as f64);
+
+            // if the destination has been modified, starting again
+            if destination.path != path {
+                destination.route = if state.get_as_boolean(&cmp, "pathfinding").unwrap_or(false) {
+                    let radius = state.get_as_number(&cmp, "radius").unwrap_or(0.0);
+                    match find_route(state, (posX, posY), path.as_slice(), radius, |l| log(l)) {
+                        Some(r) => r,
+                        None => {
+                            log(log::LogRecord::new(log::Warning, format!("no path found for destination component {}", cmp)));
+                            Vec::new()
+                        }
+                    }
+                } else {
+                    path.clone()
+                };
+
+                // the route is made available to scripts
+                let routeData = destination.route.iter()
+                    .map(|&(x, y)| List(vec!(Number(x), Number(y))))
+                    .collect();
+                state.set(&cmp, "route", List(routeData)).ok();
+
+                destination.path = path;
+                destination.nextWaypoint = 0;
+                destination.arrived = false;
+            }
+
+            // creating the "requestedMovement" component if necessary
+            let movement = match destination.movement {
+                Some(ref m) if state.get_owner(m).is_ok() => m.clone(),
+                _ => {
+                    let mut data = HashMap::new();
+                    data.insert("x".to_string(), Number(0.0));
+                    data.insert("y".to_string(), Number(0.0));
+                    let m = state.create_native_component(&owner, "requestedMovement", data).unwrap();
+                    state.set_component_parent(&m, &cmp).unwrap();
+                    destination.movement = Some(m.clone());
+                    m
+                }
+            };
+
+            let speed = state.get_as_number(&cmp, "speed").unwrap_or(1.0);
+            let tolerance = state.get_as_number(&cmp, "tolerance").unwrap_or(0.1);
+
+            // skipping the waypoints that have been reached
+            let (mut requestedX, mut requestedY) = (0.0, 0.0);
+            while destination.nextWaypoint < destination.route.len() {
+                let &(x, y) = destination.route.get(destination.nextWaypoint);
+                let (diffX, diffY) = (x - posX, y - posY);
+                let distance = (diffX * diffX + diffY * diffY).sqrt();
+
+                if distance <= tolerance {
+                    destination.nextWaypoint += 1;
+                    continue;
+                }
+
+                // slowing down in order not to miss the waypoint
+                let speed = if *elapsed > 0.0 && distance / *elapsed < speed { distance / *elapsed } else { speed };
+                requestedX = diffX * speed / distance;
+                requestedY = diffY * speed / distance;
+                break;
+            }
+
+            state.set(&movement, "x", Number(requestedX)).ok();
+            state.set(&movement, "y", Number(requestedY)).ok();
+
+            // if no path was found, the entity doesn't move and never arrives
+            if destination.route.len() == 0 {
+                continue
+            }
+
+            if destination.nextWaypoint >= destination.route.len() && !destination.arrived {
+                destination.arrived = true;
+
+                match state.get_as_string(&cmp, "scriptOnArrival") {
+                    Some(script) => { script::execute_mut(state, None, &cmp, &script.as_slice()).unwrap(); },
+                    None => ()
+                };
+            }
+        }
+    }
+}
+
+/// Returns the list of points that the entity must go through.
+/// The waypoints come first, then the destination point if there is one.
+fn read_path(state: &EntitiesState, cmp: &ComponentID)
+    -> Vec<(f64, f64)>
+{
+    let mut path: Vec<(f64, f64)> = match state.get_and_resolve(cmp, "waypoints") {
+        Ok(List(list)) => list.iter()
+            .filter_map(|point| match point {
+                &List(ref coords) if coords.len() >= 2 =>
+                    match (coords.get(0), coords.get(1)) {
+                        (&Number(x), &Number(y)) => Some((x, y)),
+                        _ => None
+                    },
+                _ => None
+            })
+            .collect(),
+        _ => Vec::new()
+    };
+
+    match (state.get_as_number(cmp, "x"), state.get_as_number(cmp, "y")) {
+        (Some(x), Some(y)) => path.push((x, y)),
+        _ => ()
+    };
+
+    path
+}
+
+/// Uses the navigation grid to find a route going through all the points of the path.
+fn find_route(state: &EntitiesState, from: (f64, f64), path: &[(f64, f64)], radius: f64,
+              log: |log::LogRecord|)
+    -> Option<Vec<(f64, f64)>>
+{
+    let mut points = vec!(from);
+    points.push_all(path);
+
+    let grid = match NavigationGrid::from_state(state, points.as_slice(), radius, log) {
+        Some(g) => g,
+        None => return None
+    };
+
+    let mut route = Vec::new();
+    let mut previous = from;
+    for point in path.iter() {
+        match grid.find_path(previous, *point) {
+            Some(p) => route.push_all(p.as_slice()),
+            None => return None
+        };
+        previous = *point;
+    }
+
+    Some(route)
+}
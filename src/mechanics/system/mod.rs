@@ -2,14 +2,36 @@ use entities::{ EntitiesState, EntitiesHelper, EntityID, ComponentID, NativeComp
 use std::collections::{ HashSet, HashMap };
 
 use resources::ResourcesLoader;
+use self::child_entity::ChildEntitySystem;
+use self::destination::DestinationSystem;
 use self::extern_content::ExternContentSystem;
+use self::property_range::PropertyRangeSystem;
 
 use log;
+use script;
 
+mod child_entity;
+mod destination;
 mod extern_content;
+mod property_range;
 
 pub struct MechanicsSystem {
+    childEntitySystem: ChildEntitySystem,
+    destinationSystem: DestinationSystem,
     externContentSystem: ExternContentSystem,
+    propertyRangeSystem: PropertyRangeSystem,
+
+    // "timedDestruction" components whose prototype has already been applied
+    startedTimedDestructions: HashSet<ComponentID>,
+
+    spawners: HashMap<ComponentID, SpawnerState>,
+}
+
+struct SpawnerState {
+    // entities spawned by the spawner that may still be alive
+    entities: Vec<EntityID>,
+    // total number of entities spawned
+    count: uint
 }
 
 impl MechanicsSystem {
@@ -17,14 +39,94 @@ impl MechanicsSystem {
         -> MechanicsSystem
     {
         MechanicsSystem {
-            externContentSystem: ExternContentSystem::new(state, loader, |l| log(l))
+            childEntitySystem: ChildEntitySystem::new(state, |l| log(l)),
+            destinationSystem: DestinationSystem::new(state, |l| log(l)),
+            externContentSystem: ExternContentSystem::new(state, loader, |l| log(l)),
+            propertyRangeSystem: PropertyRangeSystem::new(state, |l| log(l)),
+            startedTimedDestructions: HashSet::new(),
+            spawners: HashMap::new()
         }
     }
 
     pub fn process(&mut self, state: &mut EntitiesState, elapsed: &f64, log: |log::LogRecord|)
     {
+        self.update_execute_now(state, |l| log(l));
+        self.childEntitySystem.process(state, |l| log(l));
         self.externContentSystem.process(state, |l| log(l));
         self.update_spawners(state, elapsed, |l| log(l));
+        self.update_timed_destructions(state, elapsed, |l| log(l));
+        self.destinationSystem.process(state, elapsed, |l| log(l));
+        self.propertyRangeSystem.process(state, |l| log(l));
+    }
+
+    fn update_execute_now(&mut self, state: &mut EntitiesState, log: |log::LogRecord|)
+    {
+        let mut listOfComponents = state.get_visible_native_components("executeNow");
+
+        // executing the scripts in the order in which the components were created
+        listOfComponents.sort();
+
+        for cmp in listOfComponents.move_iter() {
+            // the component may have been destroyed by a previous script
+            if state.get_owner(&cmp).is_err() {
+                continue
+            }
+
+            match state.get_as_string(&cmp, "script") {
+                Some(script) => { script::execute_mut(state, None, &cmp, &script.as_slice()).unwrap(); },
+                None => log(log::LogRecord::new(log::Warning, format!("executeNow component {} has no valid \"script\" element", cmp)))
+            };
+
+            state.destroy_component(&cmp).ok();
+        }
+    }
+
+    fn update_timed_destructions(&mut self, state: &mut EntitiesState, elapsed: &f64, log: |log::LogRecord|)
+    {
+        let listOfComponents = state.get_visible_native_components("timedDestruction");
+
+        // forgetting the components that have disappeared
+        self.startedTimedDestructions = self.startedTimedDestructions.iter()
+            .filter(|c| listOfComponents.contains(*c))
+            .map(|c| c.clone())
+            .collect();
+
+        for cmp in listOfComponents.move_iter() {
+            // the component may have been destroyed with another one
+            let owner = match state.get_owner(&cmp) { Ok(o) => o, Err(_) => continue };
+
+            let time = match state.get_as_number(&cmp, "time") {
+                Some(t) => t,
+                None => {
+                    log(log::LogRecord::new(log::Warning, format!("timedDestruction component {} has no valid \"time\" element", cmp)));
+                    continue
+                }
+            };
+
+            // applying the prototype the first time we see the component
+            if !self.startedTimedDestructions.contains(&cmp) {
+                self.startedTimedDestructions.insert(cmp.clone());
+
+                match state.get_as_entity(&cmp, "prototype") {
+                    Some(prototype) => {
+                        let newCmp = state.create_component_from_entity(&owner, &prototype,
+                            HashMap::new()).unwrap();
+                        state.set_component_parent(&newCmp, &cmp);
+                    },
+                    None => ()
+                };
+            }
+
+            let time = time - *elapsed;
+
+            // destroying the component also destroys the prototype, which is one of its children
+            if time <= 0.0 {
+                state.destroy_component(&cmp).ok();
+                self.startedTimedDestructions.remove(&cmp);
+            } else {
+                state.set(&cmp, "time", ::entities::Number(time)).ok();
+            }
+        }
     }
 
     fn update_spawners(&mut self, state: &mut EntitiesState, elapsed: &f64, log: |log::LogRecord|)
@@ -32,21 +134,55 @@ impl MechanicsSystem {
         // getting the list of all sprite displayer components
         let listOfComponents = state.get_visible_native_components("spawner");
 
+        // forgetting the spawners that have disappeared
+        {
+            let toRemove = self.spawners.keys()
+                .filter(|c| !listOfComponents.contains(*c))
+                .map(|c| c.clone())
+                .collect::<Vec<ComponentID>>();
+
+            for c in toRemove.move_iter() {
+                self.spawners.remove(&c);
+            }
+        }
+
         for cmp in listOfComponents.move_iter() {
+            // the spawner may have been destroyed along with another component
+            if state.get_owner(&cmp).is_err() {
+                continue
+            }
+
             let mut nextSpawn = match state.get_as_number(&cmp, "nextSpawn") { Some(v) => v, None => continue };
             let mut interval = match state.get_as_number(&cmp, "interval") { Some(v) => v, None => continue };
             let mut limit = state.get_as_number(&cmp, "limit");
+            let maxAlive = state.get_as_number(&cmp, "maxAlive");
+            let detach = state.get_as_boolean(&cmp, "detach").unwrap_or(false);
+            let destroyAfterExpiration = state.get_as_boolean(&cmp, "destroyAfterExpiration").unwrap_or(true);
 
             // detecting infinity
-            if interval <= 0.0 && limit.is_none() {
-                // TODO: error
+            if interval <= 0.0 && limit.is_none() && maxAlive.is_none() {
+                log(log::LogRecord::new(log::Error, format!("spawner component {} would spawn an infinite number of entities", cmp)));
                 continue;
             }
 
+            // removing the spawned entities that have been destroyed
+            let mut alive = {
+                let spawner = self.spawners.find_or_insert(cmp.clone(), SpawnerState { entities: Vec::new(), count: 0 });
+                spawner.entities.retain(|e| state.is_entity_visible(e).is_ok());
+                spawner.entities.len()
+            };
+
             nextSpawn -= *elapsed as f64;
 
             while nextSpawn <= 0.0 && match limit { Some(l) => l >= 1.0, None => true } {
-                MechanicsSystem::trigger_spawner(state, &cmp);
+                // if too many entities are alive, the next one will be spawned as soon as one is destroyed
+                if maxAlive.map(|m| (alive as f64) >= m).unwrap_or(false) {
+                    nextSpawn = 0.0;
+                    break;
+                }
+
+                self.trigger_spawner(state, &cmp, detach);
+                alive += 1;
 
                 // adding interval
                 nextSpawn += interval;
@@ -67,9 +203,11 @@ impl MechanicsSystem {
             };
 
             // if limit is 0, destroying
+            // entities which are not detached would be destroyed with the spawner, so we wait for them to die
             match limit {
-                Some(v) if v <= 0.0 => {
+                Some(v) if v <= 0.0 && destroyAfterExpiration && (detach || alive == 0) => {
                     state.destroy_component(&cmp);
+                    self.spawners.remove(&cmp);
                 },
                 _ => ()
             };
@@ -78,11 +216,125 @@ impl MechanicsSystem {
 
     /// Spawns an entity on a spawner.
     /// Does not update any of the component's properties.
-    fn trigger_spawner(state: &mut EntitiesState, cmp: &ComponentID)
+    fn trigger_spawner(&mut self, state: &mut EntitiesState, cmp: &ComponentID, detach: bool)
     {
         let prototype = match state.get_as_entity(cmp, "prototype") { Some(v) => v, None => return };
+        let owner = state.get_owner(cmp).unwrap();
+
+        let spawner = self.spawners.find_or_insert(cmp.clone(), SpawnerState { entities: Vec::new(), count: 0 });
+
+        // "{}" in the pattern is replaced by the number of entities spawned so far
+        let name = state.get_as_string(cmp, "namePattern")
+            .map(|p| p.replace("{}", format!("{}", spawner.count).as_slice()));
+        spawner.count += 1;
+
+        let newEntity = state.create_entity(name, true);
+
+        // placing the new entity relatively to the spawner
+        let positionData = {
+            let position = ::physics::get_entity_position(state, &owner);
+
+            let spread = |field: &str| match state.get_as_number(cmp, field) {
+                Some(s) if s != 0.0 => (::std::rand::random::<f64>() * 2.0 - 1.0) * s,
+                _ => 0.0
+            };
+
+            let x = position.x as f64 + state.get_as_number(cmp, "offsetX").unwrap_or(0.0) + spread("spreadX");
+            let y = position.y as f64 + state.get_as_number(cmp, "offsetY").unwrap_or(0.0) + spread("spreadY");
+
+            let mut data = HashMap::new();
+            data.insert("x".to_string(), ::entities::Number(x));
+            data.insert("y".to_string(), ::entities::Number(y));
+            data.insert("z".to_string(), ::entities::Number(position.z as f64));
+            data
+        };
+
+        state.create_native_component(&newEntity, "position", positionData).unwrap();
+
+        state.create_component_from_entity(&newEntity, &prototype, HashMap::new());
+
+        if !detach {
+            state.attach_entity_to_component(&newEntity, cmp).unwrap();
+        }
+
+        spawner.entities.push(newEntity);
+    }
+}
+
+/// Returns the factor by which the elapsed time must be multiplied,
+/// according to the "timeModifier" components.
+pub fn get_time_modifier(state: &EntitiesState)
+    -> f64
+{
+    state
+        .get_visible_native_components("timeModifier")
+        .move_iter()
+        .filter_map(|c| state.get_as_number(&c, "modifier"))
+        // a negative modifier would make the time go backward
+        .map(|m| if m < 0.0 { 0.0 } else { m })
+        .fold(1.0, |a, b| a * b)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{ IoResult, Reader };
+    use entities::{ EntitiesState, EntitiesHelper, Entity, Number, String };
+    use resources::ResourcesLoader;
+    use physics;
+    use super::MechanicsSystem;
+
+    #[deriving(Clone)]
+    struct NoResources;
+
+    impl ResourcesLoader for NoResources {
+        fn load(&self, _: &str) -> IoResult<Box<Reader>> {
+            Err(::std::io::standard_error(::std::io::FileNotFound))
+        }
+    }
+
+    #[test]
+    fn spawner() {
+        let mut state = EntitiesState::new();
+        let prototype = state.create_entity(None, false);
+        let owner = state.create_entity(None, true);
+
+        let mut data = HashMap::new();
+        data.insert("x".to_string(), Number(1.0));
+        data.insert("y".to_string(), Number(2.0));
+        state.create_native_component(&owner, "position", data).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("nextSpawn".to_string(), Number(0.0));
+        data.insert("interval".to_string(), Number(1.0));
+        data.insert("maxAlive".to_string(), Number(2.0));
+        data.insert("prototype".to_string(), Entity(prototype.clone()));
+        data.insert("namePattern".to_string(), String("enemy{}".to_string()));
+        data.insert("offsetX".to_string(), Number(3.0));
+        let spawner = state.create_native_component(&owner, "spawner", data).unwrap();
+
+        let mut system = MechanicsSystem::new(&state, NoResources, |_|{});
+
+        system.update_spawners(&mut state, &0.0, |_|{});
+        let first = state.get_entities_by_name("enemy0");
+        assert_eq!(first.len(), 1);
+        let position = physics::get_entity_position(&state, first.get(0));
+        assert_eq!((position.x, position.y), (4.0, 2.0));
+
+        // only two entities can be alive at the same time
+        system.update_spawners(&mut state, &5.0, |_|{});
+        assert_eq!(state.get_entities_by_name("enemy1").len(), 1);
+        assert_eq!(state.get_entities_by_name("enemy2").len(), 0);
+
+        state.destroy_entity(first.get(0)).unwrap();
+        system.update_spawners(&mut state, &0.0, |_|{});
+        let last = state.get_entities_by_name("enemy2");
+        assert_eq!(last.len(), 1);
 
-        let newEntity = state.create_entity(None, true);
-        state.create_component_from_entity(&newEntity, &prototype, ::std::collections::HashMap::new());
+        // the entities are not detached by default, so they are destroyed with the spawner
+        state.destroy_component(&spawner).unwrap();
+        assert!(state.is_entity_visible(last.get(0)).is_err());
+        assert_eq!(state.get_entities_by_name("enemy1").len(), 0);
     }
 }
@@ -0,0 +1,111 @@
+use entities::{ EntitiesState, EntitiesHelper, ComponentID };
+use std::collections::HashMap;
+use script;
+use log;
+
+pub struct PropertyRangeSystem {
+    // last known state of each "propertyRange" component
+    ranges: HashMap<ComponentID, RangeState>
+}
+
+struct RangeState {
+    // revision of the property when it was last checked
+    revision: Option<uint>,
+    minValue: Option<f64>,
+    maxValue: Option<f64>,
+    inRange: bool
+}
+
+impl PropertyRangeSystem {
+    pub fn new(_: &EntitiesState, log: |log::LogRecord|)
+        -> PropertyRangeSystem
+    {
+        PropertyRangeSystem {
+            ranges: HashMap::new()
+        }
+    }
+
+    pub fn process(&mut self, state: &mut EntitiesState, log: |log::LogRecord|)
+    {
+        let listOfComponents = state.get_visible_native_components("propertyRange");
+
+        // forgetting the components that have disappeared
+        {
+            let toRemove = self.ranges.keys()
+                .filter(|c| !listOfComponents.contains(*c))
+                .map(|c| c.clone())
+                .collect::<Vec<ComponentID>>();
+
+            for c in toRemove.move_iter() {
+                self.ranges.remove(&c);
+            }
+        }
+
+        for cmp in listOfComponents.move_iter() {
+            // the component may have been destroyed by a script in the meantime
+            let owner = match state.get_owner(&cmp) { Ok(o) => o, Err(_) => continue };
+
+            let property = match state.get_as_string(&cmp, "property") {
+                Some(p) => p,
+                None => {
+                    log(log::LogRecord::new(log::Warning, format!("propertyRange component {} has no valid \"property\" element", cmp)));
+                    continue
+                }
+            };
+
+            let minValue = state.get_as_number(&cmp, "minValue");
+            let maxValue = state.get_as_number(&cmp, "maxValue");
+            let revision = state.get_property_revision(&owner, property.as_slice());
+
+            // skipping the components whose property and bounds have not changed
+            let wasInRange = match self.ranges.find(&cmp) {
+                Some(r) if r.revision.is_some() && r.revision == revision &&
+                           r.minValue == minValue && r.maxValue == maxValue => continue,
+                Some(r) => r.inRange,
+                None => false
+            };
+
+            let inRange = match state.get_property_as_number(&owner, property.as_slice()) {
+                Some(v) => minValue.map(|m| v >= m).unwrap_or(true) && maxValue.map(|m| v <= m).unwrap_or(true),
+                None => false
+            };
+
+            self.ranges.insert(cmp.clone(), RangeState {
+                revision: revision,
+                minValue: minValue,
+                maxValue: maxValue,
+                inRange: inRange
+            });
+
+            if inRange && !wasInRange {
+                // adding prototype
+                match state.get_as_entity(&cmp, "prototypeInRange") {
+                    Some(prototype) => {
+                        let newCmp = state.create_component_from_entity(&owner, &prototype,
+                            HashMap::new()).unwrap();
+                        state.set_component_parent(&newCmp, &cmp);
+                    },
+                    None => ()
+                };
+
+                // executing onEnter script
+                match state.get_as_string(&cmp, "scriptOnEnter") {
+                    Some(script) => { script::execute_mut(state, None, &cmp, &script.as_slice()).unwrap(); },
+                    None => ()
+                };
+
+            } else if !inRange && wasInRange {
+                // removing all its children (ie. the prototype)
+                for c in state.get_component_children(&cmp).unwrap_or(Vec::new()).move_iter() {
+                    state.destroy_component(&c).ok();
+                }
+
+                // executing onLeave script
+                match state.get_as_string(&cmp, "scriptOnLeave") {
+                    Some(script) => { script::execute_mut(state, None, &cmp, &script.as_slice()).unwrap(); },
+                    None => ()
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,436 @@
+use entities::{ EntitiesState, EntitiesHelper };
+use std::collections::PriorityQueue;
+use physics;
+use log;
+
+/// Grid of cells telling where entities can walk.
+pub struct NavigationGrid {
+    // coordinates of the bottom-left corner of the grid
+    originX: f64,
+    originY: f64,
+    cellSize: f64,
+    width: uint,
+    height: uint,
+    // true if the cell is blocked, row by row starting from the bottom
+    blocked: Vec<bool>
+}
+
+// costs of moving to a neighbour cell, multiplied by 10 in order to stay with integers
+static STRAIGHT_COST: uint = 10;
+static DIAGONAL_COST: uint = 14;
+
+static DEFAULT_CELL_SIZE: f64 = 0.5;
+
+// maximum number of cells of a grid built from the state, to avoid huge allocations
+static MAX_CELLS: uint = 1000000;
+
+// number of free cells around the obstacles and the requested points when the grid
+// size is determined automatically
+static GRID_MARGIN: uint = 4;
+
+impl NavigationGrid {
+    /// Builds a grid where all cells are walkable.
+    pub fn new(originX: f64, originY: f64, cellSize: f64, width: uint, height: uint)
+        -> NavigationGrid
+    {
+        NavigationGrid {
+            originX: originX,
+            originY: originY,
+            cellSize: cellSize,
+            width: width,
+            height: height,
+            blocked: Vec::from_elem(width * height, false)
+        }
+    }
+
+    /// Builds the grid corresponding to the obstacles of the state.
+    ///
+    /// The obstacles are the "navigationObstacle" components and the static "physics" bodies.
+    /// They are enlarged by `radius`, which should be the radius of the entity that moves.
+    /// If there is a "navigationGrid" component, it determines the area and the size of the cells.
+    /// Otherwise the grid covers all the obstacles and the given points.
+    /// Returns None if the grid would have too many cells.
+    pub fn from_state(state: &EntitiesState, points: &[(f64, f64)], radius: f64, log: |log::LogRecord|)
+        -> Option<NavigationGrid>
+    {
+        let obstacles = get_obstacles(state);
+
+        let gridCmp = state.get_visible_native_components("navigationGrid").move_iter().next();
+        let cellSize = match gridCmp.as_ref().and_then(|c| state.get_as_number(c, "cellSize")) {
+            Some(s) if s > 0.0 => s,
+            Some(_) => {
+                log(log::LogRecord::new(log::Warning, format!("navigationGrid component {} has a \"cellSize\" which is not positive", gridCmp.as_ref().unwrap())));
+                DEFAULT_CELL_SIZE
+            },
+            None => DEFAULT_CELL_SIZE
+        };
+
+        let bounds = gridCmp.as_ref().and_then(|c|
+            match (state.get_as_number(c, "leftX"), state.get_as_number(c, "bottomY"),
+                   state.get_as_number(c, "rightX"), state.get_as_number(c, "topY"))
+            {
+                (Some(l), Some(b), Some(r), Some(t)) => Some((l, b, r, t)),
+                _ => None
+            });
+
+        let (left, bottom, right, top) = match bounds {
+            Some(b) => b,
+            None => {
+                let margin = GRID_MARGIN as f64 * cellSize + radius;
+                obstacles.iter().map(|&o| o).chain(points.iter().map(|&(x, y)| (x, y, x, y)))
+                    .fold(None, |bounds: Option<(f64, f64, f64, f64)>, (l, b, r, t)| match bounds {
+                        None => Some((l, b, r, t)),
+                        Some((bl, bb, br, bt)) => Some((bl.min(l), bb.min(b), br.max(r), bt.max(t)))
+                    })
+                    .map(|(l, b, r, t)| (l - margin, b - margin, r + margin, t + margin))
+                    .unwrap_or((0.0, 0.0, 0.0, 0.0))
+            }
+        };
+
+        let width = ((right - left) / cellSize).ceil().max(0.0) + 1.0;
+        let height = ((top - bottom) / cellSize).ceil().max(0.0) + 1.0;
+
+        if width * height > MAX_CELLS as f64 {
+            log(log::LogRecord::new(log::Warning, format!("the navigation grid would have {} cells, which is more than the maximum of {} ; \"cellSize\" must be increased", width * height, MAX_CELLS)));
+            return None;
+        }
+
+        let mut grid = NavigationGrid::new(left, bottom, cellSize, width as uint, height as uint);
+        for &(l, b, r, t) in obstacles.iter() {
+            grid.block_rectangle(l - radius, b - radius, r + radius, t + radius);
+        }
+        Some(grid)
+    }
+
+    /// Marks all the cells that overlap the rectangle as blocked.
+    pub fn block_rectangle(&mut self, left: f64, bottom: f64, right: f64, top: f64)
+    {
+        let minX = ((left - self.originX) / self.cellSize).floor().max(0.0).min(self.width as f64) as uint;
+        let minY = ((bottom - self.originY) / self.cellSize).floor().max(0.0).min(self.height as f64) as uint;
+        let maxX = ((right - self.originX) / self.cellSize).ceil().max(0.0).min(self.width as f64) as uint;
+        let maxY = ((top - self.originY) / self.cellSize).ceil().max(0.0).min(self.height as f64) as uint;
+
+        for y in range(minY, maxY) {
+            for x in range(minX, maxX) {
+                *self.blocked.get_mut(y * self.width + x) = true;
+            }
+        }
+    }
+
+    /// Returns true if the cell exists and is not blocked.
+    pub fn is_walkable(&self, x: uint, y: uint)
+        -> bool
+    {
+        x < self.width && y < self.height && !*self.blocked.get(y * self.width + x)
+    }
+
+    /// Returns the cell that contains a point.
+    pub fn get_cell(&self, x: f64, y: f64)
+        -> Option<(uint, uint)>
+    {
+        let cellX = ((x - self.originX) / self.cellSize).floor();
+        let cellY = ((y - self.originY) / self.cellSize).floor();
+
+        if cellX < 0.0 || cellY < 0.0 || cellX >= self.width as f64 || cellY >= self.height as f64 {
+            return None
+        }
+
+        Some((cellX as uint, cellY as uint))
+    }
+
+    /// Returns the coordinates of the center of a cell.
+    pub fn get_cell_center(&self, x: uint, y: uint)
+        -> (f64, f64)
+    {
+        (self.originX + (x as f64 + 0.5) * self.cellSize, self.originY + (y as f64 + 0.5) * self.cellSize)
+    }
+
+    /// Finds a path between two points with the A* algorithm.
+    ///
+    /// Returns the list of waypoints to go through, the last one being `to`.
+    /// `from` is not included. Returns None if there is no path.
+    ///
+    /// The start cell is always considered walkable so that an entity standing
+    /// against an obstacle can move away from it.
+    pub fn find_path(&self, from: (f64, f64), to: (f64, f64))
+        -> Option<Vec<(f64, f64)>>
+    {
+        let (fromX, fromY) = from;
+        let (toX, toY) = to;
+
+        let start = match self.get_cell(fromX, fromY) { Some(c) => c, None => return None };
+        let goal = match self.get_cell(toX, toY) { Some(c) => c, None => return None };
+
+        if !self.is_walkable(goal.val0(), goal.val1()) {
+            return None
+        }
+
+        let index = |(x, y): (uint, uint)| y * self.width + x;
+
+        // for each cell, the cost to reach it and the cell we come from
+        let mut costs: Vec<Option<uint>> = Vec::from_elem(self.width * self.height, None);
+        let mut cameFrom: Vec<Option<(uint, uint)>> = Vec::from_elem(self.width * self.height, None);
+        let mut closed: Vec<bool> = Vec::from_elem(self.width * self.height, false);
+        let mut open = PriorityQueue::new();
+
+        *costs.get_mut(index(start)) = Some(0);
+        open.push(Node { cost: heuristic(start, goal), heuristic: heuristic(start, goal), cell: start });
+
+        loop {
+            let current = match open.pop() {
+                Some(n) => n.cell,
+                None => return None
+            };
+
+            if current == goal {
+                break
+            }
+
+            if *closed.get(index(current)) {
+                continue
+            }
+            *closed.get_mut(index(current)) = true;
+
+            let currentCost = (*costs.get(index(current))).unwrap();
+            let (cx, cy) = current;
+
+            // neighbours are always visited in the same order so that the result is deterministic
+            for &(dx, dy) in [(-1i, -1i), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)].iter() {
+                let nx = cx as int + dx;
+                let ny = cy as int + dy;
+                if nx < 0 || ny < 0 || !self.is_walkable(nx as uint, ny as uint) {
+                    continue
+                }
+
+                // forbidding to cut corners
+                if dx != 0 && dy != 0 &&
+                   (!self.is_walkable(nx as uint, cy) || !self.is_walkable(cx, ny as uint))
+                {
+                    continue
+                }
+
+                let neighbour = (nx as uint, ny as uint);
+                let cost = currentCost + if dx != 0 && dy != 0 { DIAGONAL_COST } else { STRAIGHT_COST };
+
+                match *costs.get(index(neighbour)) {
+                    Some(c) if c <= cost => continue,
+                    _ => ()
+                };
+
+                *costs.get_mut(index(neighbour)) = Some(cost);
+                *cameFrom.get_mut(index(neighbour)) = Some(current);
+
+                let h = heuristic(neighbour, goal);
+                open.push(Node { cost: cost + h, heuristic: h, cell: neighbour });
+            }
+        }
+
+        // building the list of cells from the goal to the start
+        let mut cells = Vec::new();
+        let mut current = goal;
+        while current != start {
+            cells.push(current);
+            current = (*cameFrom.get(index(current))).unwrap();
+        }
+        cells.reverse();
+
+        // keeping only the cells where the direction changes
+        let mut path = Vec::new();
+        let mut previous = start;
+        for i in range(0, cells.len()) {
+            let cell = *cells.get(i);
+
+            if i + 1 < cells.len() {
+                let next = *cells.get(i + 1);
+                let direction = (cell.val0() as int - previous.val0() as int, cell.val1() as int - previous.val1() as int);
+                let nextDirection = (next.val0() as int - cell.val0() as int, next.val1() as int - cell.val1() as int);
+                if direction != nextDirection {
+                    path.push(self.get_cell_center(cell.val0(), cell.val1()));
+                }
+            }
+
+            previous = cell;
+        }
+        path.push(to);
+
+        Some(path)
+    }
+}
+
+/// Element of the A* open list.
+#[deriving(PartialEq, Eq)]
+struct Node {
+    // estimated total cost of a path going through this cell
+    cost: uint,
+    heuristic: uint,
+    cell: (uint, uint)
+}
+
+impl Ord for Node {
+    // the priority queue is a max-heap, so the comparison is reversed
+    // ties are broken by the heuristic, then by the position of the cell
+    fn cmp(&self, other: &Node) -> Ordering {
+        match other.cost.cmp(&self.cost) {
+            Equal => match other.heuristic.cmp(&self.heuristic) {
+                Equal => other.cell.cmp(&self.cell),
+                o => o
+            },
+            o => o
+        }
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Octile distance between two cells.
+fn heuristic((ax, ay): (uint, uint), (bx, by): (uint, uint))
+    -> uint
+{
+    let dx = if ax > bx { ax - bx } else { bx - ax };
+    let dy = if ay > by { ay - by } else { by - ay };
+    let (min, max) = if dx < dy { (dx, dy) } else { (dy, dx) };
+    DIAGONAL_COST * min + STRAIGHT_COST * (max - min)
+}
+
+/// Returns the rectangles (left, bottom, right, top) of all the obstacles of the state.
+fn get_obstacles(state: &EntitiesState)
+    -> Vec<(f64, f64, f64, f64)>
+{
+    let mut obstacles = Vec::new();
+
+    for cmp in state.get_visible_native_components("navigationObstacle").iter() {
+        let owner = state.get_owner(cmp).unwrap();
+        let position = physics::get_entity_position(state, &owner);
+
+        match (state.get_as_number(cmp, "leftX"), state.get_as_number(cmp, "bottomY"),
+               state.get_as_number(cmp, "rightX"), state.get_as_number(cmp, "topY"))
+        {
+            (Some(l), Some(b), Some(r), Some(t)) =>
+                obstacles.push((l.min(r) + position.x as f64, b.min(t) + position.y as f64,
+                                l.max(r) + position.x as f64, b.max(t) + position.y as f64)),
+            _ => ()
+        }
+    }
+
+    // static bodies
+    for cmp in state.get_visible_native_components("physics").iter() {
+        if !state.get_as_boolean(cmp, "activated").unwrap_or(false) {
+            continue
+        }
+
+        let description = match physics::BodyDescription::from_component(state, cmp) {
+            Ok(d) => d,
+            Err(_) => continue
+        };
+
+        if description.bodyType != physics::Static {
+            continue
+        }
+
+        // infinite shapes can't be represented in the grid
+        let (l, b, r, t) = match description.get_bounds() {
+            Some(b) => b,
+            None => continue
+        };
+
+        let owner = state.get_owner(cmp).unwrap();
+        let position = physics::get_entity_position(state, &owner);
+        let (x, y) = (position.x as f64, position.y as f64);
+
+        obstacles.push((x + l, y + b, x + r, y + t));
+    }
+
+    obstacles
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use entities::{ EntitiesState, EntitiesHelper, Number };
+    use super::NavigationGrid;
+
+    #[test]
+    fn straight_line() {
+        let grid = NavigationGrid::new(0.0, 0.0, 1.0, 10, 10);
+        let path = grid.find_path((0.5, 0.5), (8.5, 0.5)).unwrap();
+        assert_eq!(path, vec!((8.5, 0.5)));
+    }
+
+    #[test]
+    fn around_wall() {
+        let mut grid = NavigationGrid::new(0.0, 0.0, 1.0, 10, 10);
+        grid.block_rectangle(4.0, 0.0, 5.0, 8.0);
+
+        let path = grid.find_path((1.5, 1.5), (8.5, 1.5)).unwrap();
+        assert_eq!(*path.last().unwrap(), (8.5, 1.5));
+
+        // no waypoint must be inside the wall
+        for &(x, y) in path.iter() {
+            let (cx, cy) = grid.get_cell(x, y).unwrap();
+            assert!(grid.is_walkable(cx, cy));
+        }
+
+        // the path must go above the wall
+        assert!(path.iter().any(|&(_, y)| y > 8.0));
+
+        // same input, same output
+        assert_eq!(grid.find_path((1.5, 1.5), (8.5, 1.5)).unwrap(), path);
+    }
+
+    #[test]
+    fn unreachable() {
+        let mut grid = NavigationGrid::new(0.0, 0.0, 1.0, 10, 10);
+        grid.block_rectangle(4.0, 0.0, 5.0, 10.0);
+        assert!(grid.find_path((1.5, 1.5), (8.5, 1.5)).is_none());
+        assert!(grid.find_path((1.5, 1.5), (4.5, 1.5)).is_none());
+        assert!(grid.find_path((1.5, 1.5), (20.0, 1.5)).is_none());
+    }
+
+    #[test]
+    fn obstacle_from_state() {
+        let mut state = EntitiesState::new();
+
+        let wall = state.create_entity(None, true);
+        let mut data = HashMap::new();
+        data.insert("leftX".to_string(), Number(-0.5));
+        data.insert("rightX".to_string(), Number(0.5));
+        data.insert("bottomY".to_string(), Number(-3.0));
+        data.insert("topY".to_string(), Number(3.0));
+        state.create_native_component(&wall, "navigationObstacle", data).unwrap();
+
+        let grid = NavigationGrid::from_state(&state, &[(-2.0, 0.0), (2.0, 0.0)], 0.0, |_|{}).unwrap();
+        let (cx, cy) = grid.get_cell(0.0, 0.0).unwrap();
+        assert!(!grid.is_walkable(cx, cy));
+
+        let path = grid.find_path((-2.0, 0.0), (2.0, 0.0)).unwrap();
+        assert!(path.len() >= 2);
+    }
+
+    #[test]
+    fn grid_limits() {
+        let mut state = EntitiesState::new();
+
+        let entity = state.create_entity(None, true);
+        let mut data = HashMap::new();
+        data.insert("cellSize".to_string(), Number(-1.0));
+        data.insert("leftX".to_string(), Number(0.0));
+        data.insert("rightX".to_string(), Number(10.0));
+        data.insert("bottomY".to_string(), Number(0.0));
+        data.insert("topY".to_string(), Number(10.0));
+        let cmp = state.create_native_component(&entity, "navigationGrid", data).unwrap();
+
+        // an invalid size is replaced by the default one
+        let mut warnings = 0u;
+        let grid = NavigationGrid::from_state(&state, &[], 0.0, |_| warnings += 1).unwrap();
+        assert_eq!(warnings, 1);
+        assert_eq!(grid.get_cell(9.9, 9.9), Some((19, 19)));
+
+        state.set(&cmp, "cellSize", Number(0.0001)).unwrap();
+        assert!(NavigationGrid::from_state(&state, &[], 0.0, |_|{}).is_none());
+    }
+}
@@ -0,0 +1,265 @@
+use entities::{ EntitiesHelper, ComponentID };
+use entities::{ List, Number };
+use nalgebra::na::Vec2;
+use ncollide::geom::{ Ball, Convex, Cuboid, Plane };
+use ncollide::geom::geom::Geom;
+use nphysics::object::RigidBody;
+use std::f64::consts::PI;
+
+/// Describes the body of an entity, as written in its "physics" component.
+/// When the description of an entity changes, its body is rebuilt.
+#[deriving(Clone, Show, PartialEq)]
+pub struct BodyDescription {
+    pub shape: Shape,
+    pub bodyType: BodyType,
+    pub mass: f64,
+    pub friction: f64,
+    pub restitution: f64,
+    // sensors detect other bodies without blocking them
+    pub sensor: bool,
+    // area of the sensor relative to the position, if different from the bounds of the shape
+    pub sensorArea: Option<(f64, f64, f64, f64)>,
+    pub layers: CollisionLayers
+}
+
+/// Layer of a body and layers that it interacts with.
+#[deriving(Clone, Show, PartialEq)]
+pub struct CollisionLayers {
+    pub layer: String,
+    // if None, interacts with all layers
+    pub mask: Option<Vec<String>>
+}
+
+impl CollisionLayers {
+    /// Returns true if bodies of the two layers can touch each other.
+    pub fn interacts_with(&self, other: &CollisionLayers)
+        -> bool
+    {
+        self.mask.as_ref().map(|m| m.contains(&other.layer)).unwrap_or(true) &&
+            other.mask.as_ref().map(|m| m.contains(&self.layer)).unwrap_or(true)
+    }
+}
+
+#[deriving(Clone, Show, PartialEq)]
+pub enum Shape {
+    BoxShape(f64, f64),             // half-extents
+    BallShape(f64),                 // radius
+    ConvexShape(Vec<(f64, f64)>),   // points relative to the position
+    PlaneShape(f64, f64)            // normal
+}
+
+#[deriving(Clone, Show, PartialEq)]
+pub enum BodyType {
+    Dynamic,
+    Static,
+    // not moved by the physics engine, only by its movement
+    Kinematic
+}
+
+impl BodyDescription {
+    /// Reads the description of a body from a "physics" component.
+    pub fn from_component<E: EntitiesHelper>(state: &E, cmp: &ComponentID)
+        -> Result<BodyDescription, String>
+    {
+        let shape = match state.get_as_string(cmp, "shape").unwrap_or("box".to_string()).as_slice() {
+            "box" => BoxShape(
+                state.get_as_number(cmp, "halfWidth").unwrap_or(0.5),
+                state.get_as_number(cmp, "halfHeight").unwrap_or(0.5)
+            ),
+            "ball" => BallShape(state.get_as_number(cmp, "radius").unwrap_or(0.5)),
+            "convex" => ConvexShape(try!(read_points(state, cmp))),
+            "plane" => PlaneShape(
+                state.get_as_number(cmp, "normalX").unwrap_or(0.0),
+                state.get_as_number(cmp, "normalY").unwrap_or(1.0)
+            ),
+            other => return Err(format!("unknown shape \"{}\"", other))
+        };
+
+        let bodyType = match state.get_as_string(cmp, "bodyType").unwrap_or("dynamic".to_string()).as_slice() {
+            "dynamic" => Dynamic,
+            "static" => Static,
+            "kinematic" => Kinematic,
+            other => return Err(format!("unknown body type \"{}\"", other))
+        };
+
+        // planes are infinite and thus can't move
+        let bodyType = match shape { PlaneShape(_, _) => Static, _ => bodyType };
+
+        Ok(BodyDescription {
+            shape: shape,
+            bodyType: bodyType,
+            mass: state.get_as_number(cmp, "mass").unwrap_or(1.0),
+            friction: state.get_as_number(cmp, "friction").unwrap_or(0.0),
+            restitution: state.get_as_number(cmp, "restitution").unwrap_or(0.5),
+            sensor: state.get_as_boolean(cmp, "sensor").unwrap_or(false),
+            sensorArea: match (state.get_as_number(cmp, "leftX"), state.get_as_number(cmp, "bottomY"),
+                               state.get_as_number(cmp, "rightX"), state.get_as_number(cmp, "topY"))
+            {
+                (Some(l), Some(b), Some(r), Some(t)) => Some((l.min(r), b.min(t), l.max(r), b.max(t))),
+                _ => None
+            },
+            layers: CollisionLayers {
+                layer: state.get_as_string(cmp, "layer").unwrap_or("default".to_string()),
+                mask: match state.get_and_resolve(cmp, "collidesWith") {
+                    Ok(List(list)) => Some(list.iter()
+                        .filter_map(|l| match l { &::entities::String(ref s) => Some(s.clone()), _ => None })
+                        .collect()),
+                    _ => None
+                }
+            }
+        })
+    }
+
+    /// Builds a new rigid body matching the description.
+    pub fn build(&self)
+        -> RigidBody
+    {
+        match self.shape {
+            BoxShape(x, y) => self.build_with(Cuboid::new(Vec2::new(x as f32, y as f32))),
+            BallShape(r) => self.build_with(Ball::new(r as f32)),
+            ConvexShape(ref points) => self.build_with(Convex::new(points.iter()
+                .map(|&(x, y)| Vec2::new(x as f32, y as f32)).collect::<Vec<Vec2<f32>>>().as_slice())),
+            PlaneShape(x, y) => self.build_with(Plane::new(Vec2::new(x as f32, y as f32)))
+        }
+    }
+
+    /// Returns the area of the shape, or None if it is infinite.
+    pub fn get_area(&self)
+        -> Option<f64>
+    {
+        match self.shape {
+            BoxShape(x, y) => Some(4.0 * x * y),
+            BallShape(r) => Some(PI * r * r),
+            ConvexShape(ref points) => {
+                // shoelace formula
+                let sum = range(0, points.len()).fold(0.0, |sum, i| {
+                    let &(x1, y1) = points.get(i);
+                    let &(x2, y2) = points.get((i + 1) % points.len());
+                    sum + x1 * y2 - x2 * y1
+                });
+                Some((sum / 2.0).abs())
+            },
+            PlaneShape(_, _) => None
+        }
+    }
+
+    /// Returns the rectangle covered by the shape relative to the position of the body,
+    ///  as (left, bottom, right, top), or None if it is infinite.
+    pub fn get_bounds(&self)
+        -> Option<(f64, f64, f64, f64)>
+    {
+        match self.shape {
+            BoxShape(x, y) => Some((-x, -y, x, y)),
+            BallShape(r) => Some((-r, -r, r, r)),
+            ConvexShape(ref points) => points.iter()
+                .fold(None, |bounds: Option<(f64, f64, f64, f64)>, &(x, y)| match bounds {
+                    None => Some((x, y, x, y)),
+                    Some((l, b, r, t)) => Some((l.min(x), b.min(y), r.max(x), t.max(y)))
+                }),
+            PlaneShape(_, _) => None
+        }
+    }
+
+    /// Returns the area where a sensor detects other bodies, relative to its position.
+    pub fn get_sensor_bounds(&self)
+        -> Option<(f64, f64, f64, f64)>
+    {
+        self.sensorArea.or_else(|| self.get_bounds())
+    }
+
+    fn build_with<G: Geom + Send + Clone>(&self, geom: G)
+        -> RigidBody
+    {
+        let (restitution, friction) = (self.restitution as f32, self.friction as f32);
+
+        match self.bodyType {
+            Dynamic => {
+                // nphysics wants a density, not a mass
+                let density = match self.get_area() {
+                    Some(area) if area > 0.0 => self.mass / area,
+                    _ => 1.0
+                };
+                RigidBody::new_dynamic(geom, density as f32, restitution, friction)
+            },
+            Static | Kinematic => RigidBody::new_static(geom, restitution, friction)
+        }
+    }
+}
+
+/// Reads the "points" element of a component, which must be a list of [x, y].
+fn read_points<E: EntitiesHelper>(state: &E, cmp: &ComponentID)
+    -> Result<Vec<(f64, f64)>, String>
+{
+    let list = match state.get_and_resolve(cmp, "points") {
+        Ok(List(list)) => list,
+        _ => return Err("convex shapes must have a \"points\" element".to_string())
+    };
+
+    let mut points = Vec::new();
+    for point in list.iter() {
+        match point {
+            &List(ref coords) if coords.len() >= 2 => match (coords.get(0), coords.get(1)) {
+                (&Number(x), &Number(y)) => points.push((x, y)),
+                _ => return Err("invalid point in \"points\"".to_string())
+            },
+            _ => return Err("invalid point in \"points\"".to_string())
+        }
+    }
+
+    if points.len() < 3 {
+        return Err("convex shapes must have at least three points".to_string());
+    }
+
+    Ok(points)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use entities::{ EntitiesState, EntitiesHelper, List, Number, String };
+    use super::{ BodyDescription, CollisionLayers, BallShape, BoxShape, ConvexShape, Dynamic, Static };
+
+    #[test]
+    fn description() {
+        let mut state = EntitiesState::new();
+        let entity = state.create_entity(None, true);
+
+        let cmp = state.create_native_component(&entity, "physics", HashMap::new()).unwrap();
+        let desc = BodyDescription::from_component(&state, &cmp).unwrap();
+        assert_eq!(desc.shape, BoxShape(0.5, 0.5));
+        assert_eq!(desc.bodyType, Dynamic);
+
+        state.set(&cmp, "shape", String("ball".to_string())).unwrap();
+        state.set(&cmp, "radius", Number(2.0)).unwrap();
+        state.set(&cmp, "bodyType", String("static".to_string())).unwrap();
+        let desc = BodyDescription::from_component(&state, &cmp).unwrap();
+        assert_eq!(desc.shape, BallShape(2.0));
+        assert_eq!(desc.bodyType, Static);
+        assert_eq!(desc.get_bounds(), Some((-2.0, -2.0, 2.0, 2.0)));
+
+        state.set(&cmp, "shape", String("convex".to_string())).unwrap();
+        assert!(BodyDescription::from_component(&state, &cmp).is_err());
+
+        state.set(&cmp, "points", List(vec!(
+            List(vec!(Number(0.0), Number(0.0))),
+            List(vec!(Number(2.0), Number(0.0))),
+            List(vec!(Number(0.0), Number(2.0)))
+        ))).unwrap();
+        let desc = BodyDescription::from_component(&state, &cmp).unwrap();
+        assert_eq!(desc.shape, ConvexShape(vec!((0.0, 0.0), (2.0, 0.0), (0.0, 2.0))));
+        assert_eq!(desc.get_area(), Some(2.0));
+    }
+
+    #[test]
+    fn layers() {
+        let player = CollisionLayers { layer: "player".to_string(), mask: None };
+        let bullet = CollisionLayers { layer: "bullet".to_string(), mask: Some(vec!("enemy".to_string())) };
+        let enemy = CollisionLayers { layer: "enemy".to_string(), mask: None };
+
+        assert!(!bullet.interacts_with(&player));
+        assert!(!player.interacts_with(&bullet));
+        assert!(bullet.interacts_with(&enemy));
+        assert!(player.interacts_with(&enemy));
+    }
+}
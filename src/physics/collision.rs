@@ -0,0 +1,282 @@
+use entities::{ EntitiesState, EntitiesHelper, EntityID, ComponentID };
+use entities::{ Empty, Entity };
+use std::collections::{ HashMap, HashSet };
+use std::cell::RefCell;
+use std::rc::Rc;
+use nalgebra::na::Translation;
+use ncollide::broad::BroadPhasePairFilter;
+use nphysics::detection::constraint::RBRB;
+use nphysics::object::RigidBody;
+use nphysics::world::World;
+use script;
+use log;
+use super::{ BodyDescription, CollisionLayers };
+
+/// Keeps track of the entities colliding with each "collisionHandler" component.
+pub struct CollisionHandlers {
+    colliding: HashMap<ComponentID, HashSet<EntityID>>
+}
+
+impl CollisionHandlers {
+    pub fn new()
+        -> CollisionHandlers
+    {
+        CollisionHandlers {
+            colliding: HashMap::new()
+        }
+    }
+
+    /// Calls the scripts of the "collisionHandler" components whose entity
+    ///  started or stopped colliding with another entity.
+    pub fn process(&mut self, state: &mut EntitiesState, contacts: &HashSet<(EntityID, EntityID)>, log: |log::LogRecord|)
+    {
+        let mut listOfComponents = state.get_visible_native_components("collisionHandler");
+        listOfComponents.sort();
+
+        // forgetting the components that have disappeared
+        {
+            let toRemove = self.colliding.keys()
+                .filter(|c| !listOfComponents.contains(*c))
+                .map(|c| c.clone())
+                .collect::<Vec<ComponentID>>();
+
+            for c in toRemove.move_iter() {
+                self.colliding.remove(&c);
+            }
+        }
+
+        for cmp in listOfComponents.move_iter() {
+            // the component may have been destroyed by a script in the meantime
+            let owner = match state.get_owner(&cmp) { Ok(o) => o, Err(_) => continue };
+
+            let current: HashSet<EntityID> = contacts.iter()
+                .filter_map(|&(ref a, ref b)|
+                    if *a == owner { Some(b.clone()) }
+                    else if *b == owner { Some(a.clone()) }
+                    else { None })
+                .filter(|other| matches_filter(state, &cmp, other))
+                .collect();
+
+            let previous = self.colliding.pop(&cmp).unwrap_or(HashSet::new());
+
+            let mut entered: Vec<EntityID> = current.difference(&previous).map(|e| e.clone()).collect();
+            let mut left: Vec<EntityID> = previous.difference(&current).map(|e| e.clone()).collect();
+            entered.sort();
+            left.sort();
+
+            // the prototype is kept as long as there is at least one collision
+            if previous.len() != 0 && current.len() == 0 {
+                for c in state.get_component_children(&cmp).unwrap_or(Vec::new()).move_iter() {
+                    state.destroy_component(&c).ok();
+                }
+            }
+
+            for other in left.move_iter() {
+                execute_script(state, &cmp, "scriptOnLeave", &other);
+            }
+
+            if previous.len() == 0 && current.len() != 0 {
+                match state.get_as_entity(&cmp, "prototypeWhileColliding") {
+                    Some(prototype) => {
+                        let newCmp = state.create_component_from_entity(&owner, &prototype,
+                            HashMap::new()).unwrap();
+                        state.set_component_parent(&newCmp, &cmp);
+                    },
+                    None => ()
+                };
+            }
+
+            for other in entered.move_iter() {
+                execute_script(state, &cmp, "scriptOnEnter", &other);
+            }
+
+            self.colliding.insert(cmp, current);
+        }
+    }
+}
+
+/// Returns the list of pairs of entities whose bodies are touching.
+/// In each pair, the first entity is the one with the lowest ID.
+pub fn get_contacts(world: &mut World, bodies: &HashMap<EntityID, Rc<RefCell<RigidBody>>>)
+    -> HashSet<(EntityID, EntityID)>
+{
+    let entities: HashMap<uint, EntityID> = bodies.iter()
+        .map(|(e, b)| (get_body_address(b), e.clone()))
+        .collect();
+
+    let mut constraints = Vec::new();
+    world.interferences(&mut constraints);
+
+    constraints.iter()
+        .filter_map(|c| match c {
+            &RBRB(ref b1, ref b2, _) =>
+                match (entities.find(&get_body_address(b1)), entities.find(&get_body_address(b2))) {
+                    (Some(e1), Some(e2)) if e1 < e2 => Some((e1.clone(), e2.clone())),
+                    (Some(e1), Some(e2)) if e2 < e1 => Some((e2.clone(), e1.clone())),
+                    _ => None
+                },
+            _ => None
+        })
+        .collect()
+}
+
+/// Returns the list of pairs of entities where one of them is a sensor that overlaps the other one.
+/// Sensors and bodies are approximated by their bounding rectangle.
+pub fn get_sensor_contacts(bodies: &HashMap<EntityID, Rc<RefCell<RigidBody>>>,
+                           descriptions: &HashMap<EntityID, BodyDescription>,
+                           depths: &HashMap<EntityID, f32>)
+    -> HashSet<(EntityID, EntityID)>
+{
+    let mut contacts = HashSet::new();
+
+    for (sensor, sensorDesc) in descriptions.iter().filter(|&(_, d)| d.sensor) {
+        let sensorRect = match get_world_bounds(bodies.find(sensor), sensorDesc.get_sensor_bounds()) {
+            Some(r) => r,
+            None => continue
+        };
+
+        for (other, otherDesc) in descriptions.iter() {
+            if other == sensor || otherDesc.sensor || !sensorDesc.layers.interacts_with(&otherDesc.layers) ||
+               depths.find(other) != depths.find(sensor)
+            {
+                continue
+            }
+
+            let (l, b, r, t) = match get_world_bounds(bodies.find(other), otherDesc.get_bounds()) {
+                Some(r) => r,
+                None => continue
+            };
+
+            let (sl, sb, sr, st) = sensorRect;
+            if l > sr || r < sl || b > st || t < sb {
+                continue
+            }
+
+            contacts.insert(if sensor < other { (sensor.clone(), other.clone()) } else { (other.clone(), sensor.clone()) });
+        }
+    }
+
+    contacts
+}
+
+/// Adds the position of a body to a rectangle relative to it.
+fn get_world_bounds(body: Option<&Rc<RefCell<RigidBody>>>, bounds: Option<(f64, f64, f64, f64)>)
+    -> Option<(f64, f64, f64, f64)>
+{
+    match (body, bounds) {
+        (Some(body), Some((l, b, r, t))) => {
+            let position = body.borrow().translation();
+            let (x, y) = (position.x as f64, position.y as f64);
+            Some((x + l, y + b, x + r, y + t))
+        },
+        _ => None
+    }
+}
+
+/// Prevents bodies whose layers don't interact or whose z are different from touching each other.
+pub struct LayersFilter {
+    // layers and z of each body, indexed by the address of the body
+    layers: Rc<RefCell<HashMap<uint, (CollisionLayers, f32)>>>
+}
+
+impl LayersFilter {
+    pub fn new(layers: Rc<RefCell<HashMap<uint, (CollisionLayers, f32)>>>)
+        -> LayersFilter
+    {
+        LayersFilter {
+            layers: layers
+        }
+    }
+}
+
+impl BroadPhasePairFilter<RigidBody> for LayersFilter {
+    fn is_pair_valid(&self, b1: &RigidBody, b2: &RigidBody)
+        -> bool
+    {
+        let layers = self.layers.borrow();
+
+        match (layers.find(&(b1 as *const RigidBody as uint)), layers.find(&(b2 as *const RigidBody as uint))) {
+            (Some(&(ref l1, z1)), Some(&(ref l2, z2))) => z1 == z2 && l1.interacts_with(l2),
+            _ => true
+        }
+    }
+}
+
+/// Returns an identifier for a body, which is the address of the RigidBody.
+pub fn get_body_address(body: &Rc<RefCell<RigidBody>>)
+    -> uint
+{
+    &*body.borrow() as *const RigidBody as uint
+}
+
+/// Returns true if the other entity passes the "filterProperty" and "filterValue" of the component.
+fn matches_filter(state: &EntitiesState, cmp: &ComponentID, other: &EntityID)
+    -> bool
+{
+    let property = match state.get_as_string(cmp, "filterProperty") {
+        Some(p) => p,
+        None => return true
+    };
+
+    let value = state.get_property_value(other, property.as_slice()).unwrap_or(Empty);
+
+    match state.get_and_resolve(cmp, "filterValue") {
+        Ok(Empty) | Err(_) => value != Empty,
+        Ok(expected) => value == expected
+    }
+}
+
+/// Executes one of the scripts of a "collisionHandler".
+/// The other entity is written in the "otherEntity" element of the component beforehand.
+fn execute_script(state: &mut EntitiesState, cmp: &ComponentID, name: &str, other: &EntityID)
+{
+    let script = match state.get_as_string(cmp, name) {
+        Some(s) => s,
+        None => return
+    };
+
+    state.set(cmp, "otherEntity", Entity(other.clone())).ok();
+    script::execute_mut(state, None, cmp, &script.as_slice()).unwrap();
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{ HashMap, HashSet };
+    use entities::{ EntitiesState, EntitiesHelper, Entity, String };
+    use super::CollisionHandlers;
+
+    #[test]
+    fn prototype_while_colliding() {
+        let mut state = EntitiesState::new();
+        let prototype = state.create_entity(None, false);
+        let player = state.create_entity(None, true);
+        let coin = state.create_entity(None, true);
+        let wall = state.create_entity(None, true);
+
+        let mut data = HashMap::new();
+        data.insert("prototypeWhileColliding".to_string(), Entity(prototype));
+        data.insert("filterProperty".to_string(), String("value".to_string()));
+        let handler = state.create_native_component(&player, "collisionHandler", data).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("property".to_string(), String("value".to_string()));
+        data.insert("value".to_string(), String("gold".to_string()));
+        state.create_native_component(&coin, "property", data).unwrap();
+
+        let mut handlers = CollisionHandlers::new();
+
+        // the wall doesn't have the property
+        let mut contacts = HashSet::new();
+        contacts.insert((player.clone(), wall.clone()));
+        handlers.process(&mut state, &contacts, |_| {});
+        assert_eq!(state.get_component_children(&handler).unwrap().len(), 0);
+
+        contacts.insert((player.clone(), coin.clone()));
+        handlers.process(&mut state, &contacts, |_| {});
+        assert_eq!(state.get_component_children(&handler).unwrap().len(), 1);
+
+        handlers.process(&mut state, &HashSet::new(), |_| {});
+        assert_eq!(state.get_component_children(&handler).unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,242 @@
+use entities::{ EntitiesState, EntitiesHelper, EntityID, ComponentID };
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+use nalgebra::na;
+use nalgebra::na::{ Iso2, Norm, Translation, Vec1, Vec2 };
+use nphysics::detection::joint::{ Anchor, BallInSocket, Fixed };
+use nphysics::object::RigidBody;
+use nphysics::world::World;
+use log;
+use super::{ BodyDescription, Dynamic };
+use super::collision::get_body_address;
+
+/// Keeps track of the joints created for each "joint" component.
+pub struct Joints {
+    joints: HashMap<ComponentID, JointState>
+}
+
+/// Description of a joint, as written in its "joint" component.
+#[deriving(Clone, Show, PartialEq)]
+struct JointDescription {
+    jointType: JointType,
+    other: EntityID,
+    // anchor relative to the owner of the component
+    anchor: (f64, f64),
+    // anchor relative to the other entity
+    otherAnchor: (f64, f64)
+}
+
+#[deriving(Clone, Show, PartialEq)]
+enum JointType {
+    FixedJoint,
+    PivotJoint,
+    // length, stiffness and damping ; a length of None means the distance when the joint was created
+    SpringJoint(Option<f64>, f64, f64)
+}
+
+struct JointState {
+    owner: EntityID,
+    description: JointDescription,
+    // addresses of the two bodies, to detect when they are rebuilt
+    bodies: (uint, uint),
+    handle: JointHandle
+}
+
+enum JointHandle {
+    FixedHandle(Rc<RefCell<Fixed>>),
+    PivotHandle(Rc<RefCell<BallInSocket>>),
+    // springs are not handled by nphysics ; contains the length of the spring
+    SpringHandle(f64)
+}
+
+impl Joints {
+    pub fn new()
+        -> Joints
+    {
+        Joints {
+            joints: HashMap::new()
+        }
+    }
+
+    /// Creates, rebuilds and removes the joints so that they match the "joint" components.
+    pub fn update(&mut self, state: &mut EntitiesState, world: &mut World,
+                  bodies: &HashMap<EntityID, Rc<RefCell<RigidBody>>>, log: |log::LogRecord|)
+    {
+        let mut listOfComponents = state.get_visible_native_components("joint");
+        listOfComponents.sort();
+
+        // removing the joints whose component has disappeared
+        {
+            let toRemove = self.joints.keys()
+                .filter(|c| !listOfComponents.contains(*c))
+                .map(|c| c.clone())
+                .collect::<Vec<ComponentID>>();
+
+            for c in toRemove.move_iter() {
+                self.remove_joint(world, &c);
+            }
+        }
+
+        for cmp in listOfComponents.move_iter() {
+            let owner = match state.get_owner(&cmp) { Ok(o) => o, Err(_) => continue };
+
+            let description = match read_description(state, &cmp) {
+                Some(d) => d,
+                None => {
+                    log(log::LogRecord::new(log::Warning, format!("joint component {} has no valid \"entity\" or \"jointType\" element", cmp)));
+                    self.remove_joint(world, &cmp);
+                    continue
+                }
+            };
+
+            // if the other entity no longer exists, the joint is destroyed with it
+            if state.is_entity_visible(&description.other).is_err() {
+                self.remove_joint(world, &cmp);
+                state.destroy_component(&cmp).ok();
+                continue
+            }
+
+            // both entities must have a body
+            let (body1, body2) = match (bodies.find(&owner), bodies.find(&description.other)) {
+                (Some(b1), Some(b2)) => (b1, b2),
+                _ => {
+                    self.remove_joint(world, &cmp);
+                    continue
+                }
+            };
+
+            let addresses = (get_body_address(body1), get_body_address(body2));
+
+            match self.joints.find(&cmp) {
+                Some(j) if j.description == description && j.bodies == addresses => continue,
+                _ => ()
+            };
+
+            self.remove_joint(world, &cmp);
+
+            let (ax, ay) = description.anchor;
+            let (ox, oy) = description.otherAnchor;
+
+            let handle = match description.jointType {
+                FixedJoint => FixedHandle(world.add_fixed(Fixed::new(
+                    Anchor::new(Some(body1.clone()), Iso2::new(Vec2::new(ax as f32, ay as f32), Vec1::new(0.0f32))),
+                    Anchor::new(Some(body2.clone()), Iso2::new(Vec2::new(ox as f32, oy as f32), Vec1::new(0.0f32)))
+                ))),
+
+                PivotJoint => PivotHandle(world.add_ball_in_socket(BallInSocket::new(
+                    Anchor::new(Some(body1.clone()), Vec2::new(ax as f32, ay as f32)),
+                    Anchor::new(Some(body2.clone()), Vec2::new(ox as f32, oy as f32))
+                ))),
+
+                SpringJoint(length, _, _) => SpringHandle(length.unwrap_or_else(||
+                    na::norm(&(get_anchor_position(body1, description.anchor) -
+                               get_anchor_position(body2, description.otherAnchor))) as f64)),
+            };
+
+            self.joints.insert(cmp, JointState {
+                owner: owner,
+                description: description,
+                bodies: addresses,
+                handle: handle
+            });
+        }
+    }
+
+    /// Modifies the speed of the bodies linked by springs.
+    /// Must be called before each step of the world.
+    pub fn apply_springs(&self, bodies: &HashMap<EntityID, Rc<RefCell<RigidBody>>>,
+                         descriptions: &HashMap<EntityID, BodyDescription>, elapsed: f64)
+    {
+        for joint in self.joints.values() {
+            let (length, stiffness, damping) = match (&joint.handle, &joint.description.jointType) {
+                (&SpringHandle(length), &SpringJoint(_, stiffness, damping)) => (length, stiffness, damping),
+                _ => continue
+            };
+
+            let (owner, other) = (&joint.owner, &joint.description.other);
+
+            let (body1, body2) = match (bodies.find(owner), bodies.find(other)) {
+                (Some(b1), Some(b2)) => (b1, b2),
+                _ => continue
+            };
+
+            let diff = get_anchor_position(body2, joint.description.otherAnchor) -
+                       get_anchor_position(body1, joint.description.anchor);
+            let distance = na::norm(&diff);
+            if distance == 0.0 {
+                continue
+            }
+            let direction = diff / distance;
+
+            // speed at which the anchors are moving away from each other
+            let relativeSpeed = na::dot(&(body2.borrow().lin_vel() - body1.borrow().lin_vel()), &direction);
+
+            let force = (stiffness * (distance as f64 - length) + damping * relativeSpeed as f64) as f32;
+
+            for &(entity, body, sign) in [(owner, body1, 1.0f32), (other, body2, -1.0f32)].iter() {
+                let mass = match descriptions.find(entity) {
+                    Some(d) if d.bodyType == Dynamic && !d.sensor => d.mass as f32,
+                    _ => continue
+                };
+
+                let velocity = body.borrow().lin_vel() + direction * (sign * force * elapsed as f32 / mass);
+                body.borrow_mut().set_lin_vel(velocity);
+            }
+        }
+    }
+
+    /// Returns the number of joints currently created from "joint" components.
+    /// Springs are counted too, although they are not added to the world.
+    #[cfg(test)]
+    pub fn get_joints_count(&self)
+        -> uint
+    {
+        self.joints.len()
+    }
+
+    fn remove_joint(&mut self, world: &mut World, cmp: &ComponentID)
+    {
+        match self.joints.pop(cmp).map(|j| j.handle) {
+            Some(FixedHandle(ref j)) => world.remove_fixed(j),
+            Some(PivotHandle(ref j)) => world.remove_ball_in_socket(j),
+            Some(SpringHandle(_)) | None => ()
+        }
+    }
+}
+
+/// Returns the position of an anchor in world coordinates.
+fn get_anchor_position(body: &Rc<RefCell<RigidBody>>, (x, y): (f64, f64))
+    -> Vec2<f32>
+{
+    body.borrow().translation() + Vec2::new(x as f32, y as f32)
+}
+
+fn read_description(state: &EntitiesState, cmp: &ComponentID)
+    -> Option<JointDescription>
+{
+    let other = match state.get_as_entity(cmp, "entity") {
+        Some(e) => e,
+        None => return None
+    };
+
+    let jointType = match state.get_as_string(cmp, "jointType").unwrap_or("fixed".to_string()).as_slice() {
+        "fixed" => FixedJoint,
+        "pivot" => PivotJoint,
+        "spring" => SpringJoint(
+            state.get_as_number(cmp, "length"),
+            state.get_as_number(cmp, "stiffness").unwrap_or(1.0),
+            state.get_as_number(cmp, "damping").unwrap_or(0.0)
+        ),
+        _ => return None
+    };
+
+    Some(JointDescription {
+        jointType: jointType,
+        other: other,
+        anchor: (state.get_as_number(cmp, "anchorX").unwrap_or(0.0),
+                 state.get_as_number(cmp, "anchorY").unwrap_or(0.0)),
+        otherAnchor: (state.get_as_number(cmp, "otherAnchorX").unwrap_or(0.0),
+                      state.get_as_number(cmp, "otherAnchorY").unwrap_or(0.0))
+    })
+}
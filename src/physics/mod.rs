@@ -1,114 +1,313 @@
 use entities::{ EntitiesState, EntitiesHelper, EntityID, ComponentID, NativeComponentType };
-use std::collections::{ HashSet, HashMap };
+use std::collections::{ HashMap, HashSet };
 use std::cell::RefCell;
 use std::rc::Rc;
 use nalgebra::na;
 use nalgebra::na::{ Norm, Translation, Vec2, Vec3 };
-use ncollide::geom::geom::Geom;
 use nphysics::world::World;
 use nphysics::object::{ RigidBody };
 use log;
 
+pub use self::body::{ BodyDescription, CollisionLayers, Dynamic, Static, Kinematic };
+pub use self::query::{ query_point, query_rectangle, query_ray };
+
+mod body;
+mod collision;
+mod joint;
+mod query;
+
+/// Acceleration applied to bodies whose movement is different from their requested movement.
+static MOVEMENT_ACCELERATION: f32 = 1.0;
+
 pub struct PhysicsSystem {
     world: World,
     bodies: HashMap<EntityID, Rc<RefCell<RigidBody>>>,
+    // description that was used to build each body
+    descriptions: HashMap<EntityID, BodyDescription>,
+    // settings currently applied to the world
+    settings: WorldSettings,
+    // layers and z of each body in the world, shared with the filter of the world
+    layers: Rc<RefCell<HashMap<uint, (CollisionLayers, f32)>>>,
+    // z of each entity with a body ; bodies with a different z never touch each other
+    depths: HashMap<EntityID, f32>,
+    // pairs of entities whose bodies are touching
+    contacts: HashSet<(EntityID, EntityID)>,
+    collisionHandlers: collision::CollisionHandlers,
+    joints: joint::Joints,
+}
+
+/// Settings of the world, as written in the "physicsWorld" component.
+#[deriving(Clone, PartialEq)]
+struct WorldSettings {
+    gravity: Vec2<f32>,
+    // number of steps into which each frame is split
+    substeps: uint,
+    // if None, bodies never go to sleep
+    sleepThreshold: Option<f32>
+}
+
+impl WorldSettings {
+    fn from_state(state: &EntitiesState, log: |log::LogRecord|)
+        -> WorldSettings
+    {
+        let components = state.get_visible_native_components("physicsWorld");
+        if components.len() >= 2 {
+            log(log::LogRecord::new(log::Warning, format!("multiple physicsWorld components, only one will be used")));
+        }
+
+        let cmp = match components.iter().min() {
+            Some(c) => c,
+            None => return WorldSettings { gravity: Vec2::new(0.0, 0.0), substeps: 1, sleepThreshold: None }
+        };
+
+        WorldSettings {
+            gravity: Vec2::new(
+                state.get_as_number(cmp, "gravityX").unwrap_or(0.0) as f32,
+                state.get_as_number(cmp, "gravityY").unwrap_or(0.0) as f32
+            ),
+            substeps: state.get_as_number(cmp, "substeps").map(|n| n.max(1.0) as uint).unwrap_or(1),
+            sleepThreshold: state.get_as_number(cmp, "sleepThreshold").map(|t| t as f32)
+        }
+    }
 }
 
 impl PhysicsSystem {
     pub fn new(_: &EntitiesState, log: |log::LogRecord|)
         -> PhysicsSystem
     {
-        let mut world = World::new();
-        world.set_gravity(Vec2::new(1.0f32, 1.0));
+        let layers = Rc::new(RefCell::new(HashMap::new()));
 
-        //let shape = ::ncollide::geom::Plane::new(Vec2::new(0.0f32, 1.0));
-        //let body = Rc::new(RefCell::new(RigidBody::new_static(shape, 0.3, 0.6)));
-        //world.add_body(body);
+        let mut world = World::new();
+        world.set_gravity(Vec2::new(0.0f32, 0.0));
+        world.add_filter(collision::LayersFilter::new(layers.clone()));
 
         PhysicsSystem {
             world: world,
-            bodies: HashMap::new()
+            bodies: HashMap::new(),
+            descriptions: HashMap::new(),
+            settings: WorldSettings { gravity: Vec2::new(0.0, 0.0), substeps: 1, sleepThreshold: None },
+            layers: layers,
+            depths: HashMap::new(),
+            contacts: HashSet::new(),
+            collisionHandlers: collision::CollisionHandlers::new(),
+            joints: joint::Joints::new()
         }
     }
 
     pub fn process(&mut self, state: &mut EntitiesState, elapsed: &f64, log: |log::LogRecord|)
     {
-        // getting the list of all entities that have physics activated
-        let listOfEntities: HashSet<EntityID> = state.get_components_iter()
+        // updating the world settings
+        {
+            let settings = WorldSettings::from_state(state, |l| log(l));
+            if settings.gravity != self.settings.gravity {
+                self.world.set_gravity(settings.gravity);
+            }
+            self.settings = settings;
+        }
+
+        // getting the list of all entities that have physics activated, with the description of their body
+        // if an entity has multiple "physics" components, the one with the lowest ID is used
+        let mut listOfComponents: Vec<ComponentID> = state.get_components_iter()
             .filter(|c| state.is_component_visible(*c).unwrap())
             .filter(|c| match state.get_type(*c) { Ok(NativeComponentType(t)) => t.as_slice() == "physics", _ => false })
             .filter(|c| match state.get_as_boolean(*c, "activated") { Some(b) => b, _ => false })
-            .map(|c| state.get_owner(c).unwrap())
+            .map(|c| c.clone())
             .collect();
+        listOfComponents.sort();
 
-        // removing from the world the elements that have disappeared
+        let mut listOfEntities: HashMap<EntityID, BodyDescription> = HashMap::new();
+        for cmp in listOfComponents.iter() {
+            let owner = state.get_owner(cmp).unwrap();
+            if listOfEntities.contains_key(&owner) {
+                continue
+            }
+
+            match BodyDescription::from_component(state, cmp) {
+                Ok(desc) => { listOfEntities.insert(owner, desc); },
+                Err(err) => log(log::LogRecord::new(log::Warning, format!("invalid physics component {}: {}", cmp, err)))
+            };
+        }
+
+        // removing from the world the elements that have disappeared or have been deactivated,
+        //  and the bodies whose description has changed so that they are rebuilt below
         {
-            let toRemove: Vec<EntityID> = self.bodies.keys().filter(|e| !listOfEntities.contains(e.clone())).map(|e| e.clone()).collect();
+            let toRemove: Vec<EntityID> = self.descriptions.iter()
+                .filter(|&(e, d)| listOfEntities.find(e).map(|n| n != d).unwrap_or(true))
+                .map(|(e, _)| e.clone())
+                .collect();
+
             for e in toRemove.move_iter() {
-                //self.bodies.find(&e).     // TODO: remove body from the world
-                self.bodies.remove(&e);
+                self.remove_body(&e);
             }
         }
 
         // adding elements that are not yet in the world
         {
-            let toCreate: Vec<EntityID> = listOfEntities.iter().filter(|e| !self.bodies.contains_key(e.clone())).map(|e| e.clone()).collect();
+            let toCreate: Vec<EntityID> = listOfEntities.keys().filter(|e| !self.bodies.contains_key(e.clone())).map(|e| e.clone()).collect();
             for e in toCreate.move_iter() {
-                let shape = ::ncollide::geom::Cuboid::new(Vec2::new(0.5f32, 0.5f32));
-                let body = Rc::new(RefCell::new(RigidBody::new_dynamic(shape, 1.0, 0.5, 0.0)));
+                let description = listOfEntities.find(&e).unwrap().clone();
+                let body = Rc::new(RefCell::new(description.build()));
 
                 // initializing body with current position and movement
                 body.borrow_mut().set_translation({ let p = get_entity_position(state, &e); na::Vec2::new(p.x,p.y) });
                 body.borrow_mut().set_lin_vel({ let p = get_entity_movement(state, &e); na::Vec2::new(p.x,p.y) });
-                body.borrow_mut().set_lin_acc_scale(na::Vec2::new(0.0, 0.0));
+
+                // sensors are not part of the world, so that they don't block anything
+                if !description.sensor {
+                    self.layers.borrow_mut().insert(collision::get_body_address(&body),
+                        (description.layers.clone(), get_entity_position(state, &e).z));
+                    self.world.add_body(body.clone());
+                }
 
                 self.bodies.insert(e.clone(), body.clone());
-                self.world.add_body(body.clone());
+                self.descriptions.insert(e.clone(), description);
             }
         }
 
         // setting all positions and movements
         for (entity, body) in self.bodies.iter() {
-            let position = { let p = get_entity_position(state, entity); na::Vec2::new(p.x,p.y) };
+            let position = get_entity_position(state, entity);
+            let (position, z) = (na::Vec2::new(position.x, position.y), position.z);
             let movement = { let p = get_entity_movement(state, entity); na::Vec2::new(p.x,p.y) };
             let requestedMovement = get_requested_movement(state, entity);
+            let description = self.descriptions.find(entity).unwrap();
+
+            self.depths.insert(entity.clone(), z);
+            match self.layers.borrow_mut().find_mut(&collision::get_body_address(body)) {
+                Some(&(_, ref mut depth)) => *depth = z,
+                None => ()
+            };
 
             let mut borrowedBody = body.borrow_mut();
             borrowedBody.set_translation(position);
+
+            // only dynamic bodies are moved by the physics engine
+            if description.bodyType != Dynamic || description.sensor {
+                continue;
+            }
+
+            // accelerating towards the requested movement
+            let movement = match requestedMovement {
+                Some(requested) => {
+                    let maxDiff = MOVEMENT_ACCELERATION * (*elapsed as f32);
+                    Vec2::new(accelerate(movement.x, requested.x, maxDiff), accelerate(movement.y, requested.y, maxDiff))
+                },
+                None => movement
+            };
+
             borrowedBody.set_lin_vel(movement);
-            borrowedBody.activate(100.0);       // objects tend to deactivate too often
-
-            if requestedMovement.is_some() {
-                let requestedMovement = requestedMovement.unwrap();
-
-                let acceleration: Vec2<f32> = Vec2::new(
-                    if requestedMovement.x < 0.0 && requestedMovement.x < movement.x { -1.0 }
-                    else if requestedMovement.x > 0.0 && requestedMovement.x > movement.x { 1.0 }
-                    else { 0.0 },
-                    if requestedMovement.y < 0.0 && requestedMovement.y < movement.y { -1.0 }
-                    else if requestedMovement.y > 0.0 && requestedMovement.y > movement.y { 1.0 }
-                    else { 0.0 }
-                );
-
-                if acceleration != borrowedBody.lin_acc_scale() {
-                    borrowedBody.set_lin_acc_scale(acceleration);
+
+            match self.settings.sleepThreshold {
+                Some(t) => borrowedBody.set_deactivation_threshold(Some(t)),
+                None => {
+                    borrowedBody.set_deactivation_threshold(None);
+                    borrowedBody.activate(100.0);       // objects tend to deactivate too often
                 }
-            }
+            };
         }
 
-        // step
-        self.world.step(*elapsed as f32);
+        // updating the joints now that all the bodies exist
+        self.joints.update(state, &mut self.world, &self.bodies, |l| log(l));
+        self.joints.apply_springs(&self.bodies, &self.descriptions, *elapsed);
+
+        // step, unless the game is paused
+        if *elapsed > 0.0 {
+            let step = *elapsed as f32 / self.settings.substeps as f32;
+            for _ in range(0, self.settings.substeps) {
+                self.world.step(step);
+            }
+
+            self.contacts = collision::get_contacts(&mut self.world, &self.bodies);
+            self.contacts.extend(collision::get_sensor_contacts(&self.bodies, &self.descriptions, &self.depths).move_iter());
+
+            // the contacts between bodies that are no longer on the same z are ignored
+            self.contacts = self.contacts.iter()
+                .filter(|&&(ref a, ref b)| self.depths.find(a) == self.depths.find(b))
+                .map(|p| p.clone())
+                .collect();
+        }
 
         //
         for (entity, body) in self.bodies.iter() {
-            set_position(state, entity, &body.borrow().translation());
-            set_movement(state, entity, &body.borrow().lin_vel());
+            let description = self.descriptions.find(entity).unwrap();
+
+            match (description.sensor, &description.bodyType) {
+                (false, &Dynamic) => {
+                    set_position(state, entity, &body.borrow().translation());
+                    set_movement(state, entity, &body.borrow().lin_vel());
+                },
+
+                (_, &Static) => (),
+
+                // kinematic bodies and sensors simply follow their movement
+                _ => {
+                    let movement = { let p = get_entity_movement(state, entity); na::Vec2::new(p.x,p.y) };
+                    let position = body.borrow().translation() + movement * (*elapsed as f32);
+                    body.borrow_mut().set_translation(position);
+                    set_position(state, entity, &position);
+                }
+            }
         }
+
+        // calling the collision handlers
+        self.collisionHandlers.process(state, &self.contacts, |l| log(l));
+    }
+
+    /// Removes the body of an entity from the world.
+    fn remove_body(&mut self, entity: &EntityID)
+    {
+        let sensor = self.descriptions.pop(entity).map(|d| d.sensor).unwrap_or(false);
+
+        match self.bodies.pop(entity) {
+            // sensors are not part of the world
+            Some(ref body) if !sensor => {
+                self.layers.borrow_mut().remove(&collision::get_body_address(body));
+                self.world.remove_body(body);
+            },
+            _ => ()
+        };
+
+        self.depths.remove(entity);
+
+        self.contacts = self.contacts.iter()
+            .filter(|&&(ref a, ref b)| a != entity && b != entity)
+            .map(|p| p.clone())
+            .collect();
+    }
+
+    /// Returns the number of bodies in the world.
+    #[cfg(test)]
+    fn get_world_bodies_count(&self)
+        -> uint
+    {
+        self.world.bodies().count()
+    }
+
+    /// Returns the number of joints created from "joint" components.
+    #[cfg(test)]
+    fn get_joints_count(&self)
+        -> uint
+    {
+        self.joints.get_joints_count()
+    }
+}
+
+/// Returns the new speed along an axis after accelerating towards the requested speed.
+/// Bodies are never slowed down when the requested speed is 0.
+fn accelerate(current: f32, requested: f32, maxDiff: f32)
+    -> f32
+{
+    if requested < 0.0 && requested < current {
+        (current - maxDiff).max(requested)
+    } else if requested > 0.0 && requested > current {
+        (current + maxDiff).min(requested)
+    } else {
+        current
     }
 }
 
 /// returns the position of an entity
-pub fn get_entity_position(state: &EntitiesState, id: &EntityID)
+pub fn get_entity_position<E: EntitiesHelper>(state: &E, id: &EntityID)
     -> na::Vec3<f32>
 {
     use std::iter::AdditiveIterator;
@@ -261,3 +460,92 @@ pub fn adjust_movement(state: &mut EntitiesState, id: &EntityID, diff: &Vec2<f32
         state.set(cmp, "y", ::entities::Number(y + diff.y as f64));
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use entities::{ EntitiesState, EntitiesHelper, Boolean, Entity, String };
+    use super::PhysicsSystem;
+
+    #[test]
+    fn bodies_removal() {
+        let mut state = EntitiesState::new();
+        let mut system = PhysicsSystem::new(&state, |_| {});
+
+        let entity = state.create_entity(None, true);
+        let mut data = HashMap::new();
+        data.insert("activated".to_string(), Boolean(true));
+        let cmp = state.create_native_component(&entity, "physics", data.clone()).unwrap();
+
+        let sensor = state.create_entity(None, true);
+        let mut sensorData = data.clone();
+        sensorData.insert("sensor".to_string(), Boolean(true));
+        state.create_native_component(&sensor, "physics", sensorData).unwrap();
+
+        system.process(&mut state, &0.1, |_| {});
+        assert_eq!(system.get_world_bodies_count(), 1);
+
+        state.set(&cmp, "activated", Boolean(false)).unwrap();
+        system.process(&mut state, &0.1, |_| {});
+        assert_eq!(system.get_world_bodies_count(), 0);
+
+        state.set(&cmp, "activated", Boolean(true)).unwrap();
+        system.process(&mut state, &0.1, |_| {});
+        assert_eq!(system.get_world_bodies_count(), 1);
+
+        state.destroy_component(&cmp).unwrap();
+        system.process(&mut state, &0.1, |_| {});
+        assert_eq!(system.get_world_bodies_count(), 0);
+
+        let other = state.create_entity(None, true);
+        state.create_native_component(&other, "physics", data.clone()).unwrap();
+        system.process(&mut state, &0.1, |_| {});
+        assert_eq!(system.get_world_bodies_count(), 1);
+
+        state.destroy_entity(&other).unwrap();
+        system.process(&mut state, &0.1, |_| {});
+        assert_eq!(system.get_world_bodies_count(), 0);
+    }
+
+    #[test]
+    fn joints_removal() {
+        let mut state = EntitiesState::new();
+        let mut system = PhysicsSystem::new(&state, |_| {});
+
+        let mut data = HashMap::new();
+        data.insert("activated".to_string(), Boolean(true));
+
+        let entity = state.create_entity(None, true);
+        state.create_native_component(&entity, "physics", data.clone()).unwrap();
+        let other = state.create_entity(None, true);
+        state.create_native_component(&other, "physics", data.clone()).unwrap();
+
+        let mut jointData = HashMap::new();
+        jointData.insert("entity".to_string(), Entity(other.clone()));
+        let joint = state.create_native_component(&entity, "joint", jointData.clone()).unwrap();
+
+        system.process(&mut state, &0.1, |_| {});
+        assert_eq!(system.get_joints_count(), 1);
+
+        // changing the type rebuilds the joint
+        state.set(&joint, "jointType", String("pivot".to_string())).unwrap();
+        system.process(&mut state, &0.1, |_| {});
+        assert_eq!(system.get_joints_count(), 1);
+
+        state.destroy_component(&joint).unwrap();
+        system.process(&mut state, &0.1, |_| {});
+        assert_eq!(system.get_joints_count(), 0);
+
+        // the joint is destroyed along with the other entity
+        jointData.insert("jointType".to_string(), String("spring".to_string()));
+        let joint = state.create_native_component(&entity, "joint", jointData.clone()).unwrap();
+        system.process(&mut state, &0.1, |_| {});
+        assert_eq!(system.get_joints_count(), 1);
+
+        state.destroy_entity(&other).unwrap();
+        system.process(&mut state, &0.1, |_| {});
+        assert_eq!(system.get_joints_count(), 0);
+        assert!(state.get_owner(&joint).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use entities::{ EntitiesHelper, EntityID };
+use input::click_box;
+use super::{ BodyDescription, get_entity_position };
+use super::body::BallShape;
+
+/// Area covered by a body or a clickBox, in world coordinates.
+#[deriving(Clone, Show, PartialEq)]
+enum Area {
+    Rectangle(f64, f64, f64, f64),      // left, bottom, right, top
+    Circle(f64, f64, f64)               // center and radius
+}
+
+impl Area {
+    fn get_center(&self)
+        -> (f64, f64)
+    {
+        match *self {
+            Rectangle(l, b, r, t) => ((l + r) / 2.0, (b + t) / 2.0),
+            Circle(x, y, _) => (x, y)
+        }
+    }
+
+    fn contains_point(&self, x: f64, y: f64)
+        -> bool
+    {
+        match *self {
+            Rectangle(l, b, r, t) => x >= l && x <= r && y >= b && y <= t,
+            Circle(cx, cy, radius) => (x - cx) * (x - cx) + (y - cy) * (y - cy) <= radius * radius
+        }
+    }
+
+    fn intersects_rectangle(&self, left: f64, bottom: f64, right: f64, top: f64)
+        -> bool
+    {
+        match *self {
+            Rectangle(l, b, r, t) => l <= right && r >= left && b <= top && t >= bottom,
+            Circle(cx, cy, radius) => {
+                // distance between the center and the closest point of the rectangle
+                let (x, y) = (cx.max(left).min(right), cy.max(bottom).min(top));
+                (x - cx) * (x - cx) + (y - cy) * (y - cy) <= radius * radius
+            }
+        }
+    }
+
+    /// Returns the distance between `from` and the point where the segment enters the area.
+    fn intersects_segment(&self, from: (f64, f64), to: (f64, f64))
+        -> Option<f64>
+    {
+        let ((fx, fy), (tx, ty)) = (from, to);
+        let (dx, dy) = (tx - fx, ty - fy);
+        let length = (dx * dx + dy * dy).sqrt();
+
+        if self.contains_point(fx, fy) {
+            return Some(0.0);
+        }
+
+        if length == 0.0 {
+            return None;
+        }
+
+        // ratio of the segment where it enters the area
+        let ratio = match *self {
+            Rectangle(l, b, r, t) => {
+                let mut min = 0.0f64;
+                let mut max = 1.0f64;
+
+                for &(origin, delta, low, high) in [(fx, dx, l, r), (fy, dy, b, t)].iter() {
+                    if delta == 0.0 {
+                        if origin < low || origin > high { return None }
+                        continue;
+                    }
+
+                    let (t1, t2) = ((low - origin) / delta, (high - origin) / delta);
+                    min = min.max(t1.min(t2));
+                    max = max.min(t1.max(t2));
+                }
+
+                if min > max { return None }
+                min
+            },
+
+            Circle(cx, cy, radius) => {
+                let (ox, oy) = (fx - cx, fy - cy);
+                let a = dx * dx + dy * dy;
+                let b = 2.0 * (ox * dx + oy * dy);
+                let c = ox * ox + oy * oy - radius * radius;
+                let discriminant = b * b - 4.0 * a * c;
+
+                if discriminant < 0.0 { return None }
+                let ratio = (-b - discriminant.sqrt()) / (2.0 * a);
+                if ratio < 0.0 || ratio > 1.0 { return None }
+                ratio
+            }
+        };
+
+        Some(ratio * length)
+    }
+}
+
+/// Returns the list of entities whose body or clickBox contains the point,
+///  sorted by the distance between the point and their center.
+pub fn query_point<E: EntitiesHelper>(state: &E, x: f64, y: f64)
+    -> Vec<EntityID>
+{
+    sort_by_distance(get_areas(state).move_iter()
+        .filter(|&(_, ref area)| area.contains_point(x, y))
+        .map(|(e, area)| { let (cx, cy) = area.get_center(); (e, distance((x, y), (cx, cy))) })
+        .collect())
+}
+
+/// Returns the list of entities whose body or clickBox intersects the rectangle,
+///  sorted by the distance between the center of the rectangle and their center.
+pub fn query_rectangle<E: EntitiesHelper>(state: &E, left: f64, bottom: f64, right: f64, top: f64)
+    -> Vec<EntityID>
+{
+    let (left, right) = (left.min(right), left.max(right));
+    let (bottom, top) = (bottom.min(top), bottom.max(top));
+    let center = ((left + right) / 2.0, (bottom + top) / 2.0);
+
+    sort_by_distance(get_areas(state).move_iter()
+        .filter(|&(_, ref area)| area.intersects_rectangle(left, bottom, right, top))
+        .map(|(e, area)| (e, distance(center, area.get_center())))
+        .collect())
+}
+
+/// Returns the list of entities whose body or clickBox is crossed by the segment between
+///  `from` and `to`, sorted by the distance between `from` and the point where the segment
+///  enters them.
+pub fn query_ray<E: EntitiesHelper>(state: &E, from: (f64, f64), to: (f64, f64))
+    -> Vec<EntityID>
+{
+    sort_by_distance(get_areas(state).move_iter()
+        .filter_map(|(e, area)| area.intersects_segment(from, to).map(|d| (e, d)))
+        .collect())
+}
+
+/// Returns the areas of all the activated bodies and of all the clickBoxes.
+fn get_areas<E: EntitiesHelper>(state: &E)
+    -> Vec<(EntityID, Area)>
+{
+    let mut areas = Vec::new();
+
+    for cmp in state.get_visible_native_components("physics").iter() {
+        if !state.get_as_boolean(cmp, "activated").unwrap_or(false) {
+            continue
+        }
+
+        let description = match BodyDescription::from_component(state, cmp) {
+            Ok(d) => d,
+            Err(_) => continue
+        };
+
+        let owner = state.get_owner(cmp).unwrap();
+        let position = get_entity_position(state, &owner);
+        let (x, y) = (position.x as f64, position.y as f64);
+
+        let area = match (&description.shape, description.get_bounds()) {
+            (&BallShape(radius), _) => Circle(x, y, radius),
+            // other shapes are approximated by the rectangle around them
+            (_, Some((l, b, r, t))) => Rectangle(x + l, y + b, x + r, y + t),
+            // planes are infinite
+            (_, None) => continue
+        };
+
+        areas.push((owner, area));
+    }
+
+    for (_, owner, shape) in click_box::get_click_shapes(state).move_iter() {
+        areas.push((owner, match shape {
+            click_box::Circle(x, y, radius) => Circle(x, y, radius),
+            // polygons are approximated by the rectangle around them
+            other => { let (l, b, r, t) = other.get_bounds(); Rectangle(l, b, r, t) }
+        }));
+    }
+
+    areas
+}
+
+/// Sorts the entities by distance, then by ID for the entities at the same distance.
+/// Only the closest distance is kept for entities which are present multiple times.
+fn sort_by_distance(mut list: Vec<(EntityID, f64)>)
+    -> Vec<EntityID>
+{
+    list.sort_by(|&(ref e1, d1), &(ref e2, d2)| match d1.partial_cmp(&d2) {
+        Some(Equal) | None => e1.cmp(e2),
+        Some(o) => o
+    });
+
+    let mut result: Vec<EntityID> = Vec::new();
+    for (e, _) in list.move_iter() {
+        if !result.contains(&e) {
+            result.push(e);
+        }
+    }
+    result
+}
+
+fn distance((x1, y1): (f64, f64), (x2, y2): (f64, f64))
+    -> f64
+{
+    ((x2 - x1) * (x2 - x1) + (y2 - y1) * (y2 - y1)).sqrt()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use entities::{ EntitiesState, EntitiesHelper, EntityID, Boolean, Number, String };
+    use super::{ query_point, query_rectangle, query_ray };
+
+    fn create_ball(state: &mut EntitiesState, x: f64, y: f64)
+        -> EntityID
+    {
+        let entity = state.create_entity(None, true);
+
+        let mut data = HashMap::new();
+        data.insert("x".to_string(), Number(x));
+        data.insert("y".to_string(), Number(y));
+        state.create_native_component(&entity, "position", data).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("activated".to_string(), Boolean(true));
+        data.insert("shape".to_string(), String("ball".to_string()));
+        data.insert("radius".to_string(), Number(1.0));
+        state.create_native_component(&entity, "physics", data).unwrap();
+
+        entity
+    }
+
+    #[test]
+    fn queries() {
+        let mut state = EntitiesState::new();
+        let a = create_ball(&mut state, 0.0, 0.0);
+        let b = create_ball(&mut state, 5.0, 0.0);
+        let c = create_ball(&mut state, 10.0, 0.0);
+
+        assert_eq!(query_point(&state, 0.5, 0.5), vec!(a.clone()));
+        // inside the bounding rectangle, but not inside the circle
+        assert_eq!(query_point(&state, 5.9, 0.9), vec!());
+
+        assert_eq!(query_rectangle(&state, 3.0, -1.0, 14.0, 1.0), vec!(c.clone(), b.clone()));
+
+        assert_eq!(query_ray(&state, (-5.0, 0.0), (20.0, 0.0)), vec!(a.clone(), b.clone(), c.clone()));
+        assert_eq!(query_ray(&state, (20.0, 0.0), (4.5, 0.0)), vec!(c.clone(), b.clone()));
+        assert_eq!(query_ray(&state, (-5.0, 3.0), (20.0, 3.0)), vec!());
+    }
+}
@@ -1,8 +1,12 @@
 use entities::{ EntitiesHelper, EntitiesState, EntityID, ComponentID };
+use input::PressedElements;
 use lua::{ Lua, LuaError };
 use lua::any;
 
-pub fn execute_mut<E: EntitiesHelper, S: ::std::str::Str + ::std::fmt::Show>(entities: &mut E, component: &ComponentID, code: &S)
+/// Executes a script which can modify the entities.
+/// `input` is the state of the input, which is only given to the scripts executed by the input system.
+pub fn execute_mut<E: EntitiesHelper, S: ::std::str::Str + ::std::fmt::Show>(entities: &mut E, input: Option<&PressedElements>,
+                                                                            component: &ComponentID, code: &S)
     -> Result<any::AnyLuaValue, LuaError>
 {
     let mut lua = Lua::new();
@@ -10,6 +14,11 @@ pub fn execute_mut<E: EntitiesHelper, S: ::std::str::Str + ::std::fmt::Show>(ent
     println!("executing script {}", code);
 
     //lua.set("Entities", );
+    register_queries(&mut lua, &*entities);
+    match input {
+        Some(pressed) => register_input(&mut lua, pressed),
+        None => ()
+    };
 
     lua.execute(code.as_slice())
 }
@@ -22,6 +31,37 @@ pub fn execute<E: EntitiesHelper, S: ::std::str::Str + ::std::fmt::Show>(entitie
     println!("executing script {}", code);
 
     //lua.set("Entities", );
+    register_queries(&mut lua, &*entities);
 
     lua.execute(code.as_slice())
 }
+
+/// Gives the scripts access to the spatial queries of the physics module.
+/// Each function returns the list of the entities found, the closest one first.
+fn register_queries<E: EntitiesHelper>(lua: &mut Lua, entities: &E)
+{
+    lua.set("queryPoint", |x: f64, y: f64|
+        to_numbers(::physics::query_point(entities, x, y)));
+
+    lua.set("queryRectangle", |left: f64, bottom: f64, right: f64, top: f64|
+        to_numbers(::physics::query_rectangle(entities, left, bottom, right, top)));
+
+    lua.set("queryRay", |fromX: f64, fromY: f64, toX: f64, toY: f64|
+        to_numbers(::physics::query_ray(entities, (fromX, fromY), (toX, toY))));
+}
+
+/// Gives the scripts access to the state of the input.
+fn register_input(lua: &mut Lua, pressed: &PressedElements)
+{
+    lua.set("isPressed", |name: String| pressed.is_pressed(name.as_slice()));
+
+    // returns 0 if the element or action is not down
+    lua.set("pressedDuration", |name: String| pressed.pressed_duration(name.as_slice()).unwrap_or(0.0));
+}
+
+/// Converts a list of entities to something that can be passed to Lua.
+fn to_numbers(entities: Vec<EntityID>)
+    -> Vec<uint>
+{
+    entities.iter().map(|e| e.to_uint()).collect()
+}